@@ -0,0 +1,5 @@
+// The bindings for the Deno target initialize the WASM module when they are imported, so there is
+// no `init()` to call.
+import { greet } from "./dist/deno-example.js";
+
+console.log(greet("Deno"));
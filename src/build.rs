@@ -1,17 +1,18 @@
 //! Build system & asset pipelines.
 
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use futures_util::stream::StreamExt;
 use tokio::fs;
 use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReadDirStream;
 
-use crate::common::{remove_dir_all, BUILDING, ERROR, SUCCESS};
+use crate::common::{path_exists, remove_dir_all, sha256, BUILDING, ERROR, SUCCESS};
 use crate::config::{RtcBuild, STAGE_DIR};
-use crate::pipelines::HtmlPipeline;
+use crate::pipelines::{HtmlPipeline, StageTiming};
 
 /// A system used for building a Rust WASM app & bundling its assets.
 ///
@@ -24,6 +25,8 @@ pub struct BuildSystem {
     cfg: Arc<RtcBuild>,
     /// HTML build pipeline.
     html_pipeline: Arc<HtmlPipeline>,
+    /// The time each stage of the last successful build took.
+    timings: Vec<StageTiming>,
 }
 
 impl BuildSystem {
@@ -36,7 +39,11 @@ impl BuildSystem {
         ignore_chan: Option<mpsc::Sender<PathBuf>>,
     ) -> Result<Self> {
         let html_pipeline = Arc::new(HtmlPipeline::new(cfg.clone(), ignore_chan)?);
-        Ok(Self { cfg, html_pipeline })
+        Ok(Self {
+            cfg,
+            html_pipeline,
+            timings: vec![],
+        })
     }
 
     /// Build the application described in the given build data.
@@ -67,22 +74,81 @@ impl BuildSystem {
             .await
             .context("error preparing build environment")?;
 
+        // The wasm-opt debug output only covers the current build.
+        if self.cfg.wasm_opt_debug_output {
+            let debug_file = self.cfg.wasm_opt_debug_file().await?;
+            if path_exists(&debug_file).await? {
+                fs::remove_file(&debug_file)
+                    .await
+                    .context("error removing previous wasm-opt debug output")?;
+            }
+        }
+
         // Spawn the source HTML pipeline. This will spawn all other pipelines derived from
         // the source HTML, and will ultimately generate and write the final HTML.
-        self.html_pipeline
+        let timings = self
+            .html_pipeline
             .clone()
             .spawn()
             .await
             .context("error joining HTML pipeline")?
             .context("error from HTML pipeline")?;
 
+        // Record the hashes of all output files, and compare them to the previous build if asked to.
+        self.write_output_manifest()
+            .await
+            .context("error writing output manifest")?;
+
         // Move distribution from staging dist to final dist
         self.finalize_dist()
             .await
             .context("error applying built distribution")?;
+        self.timings = timings;
         Ok(())
     }
 
+    /// The time each stage of the last successful build took.
+    pub fn timings(&self) -> &[StageTiming] {
+        &self.timings
+    }
+
+    /// Write the SHA-256 hashes of all files in the staging dist dir to its `.trunk-manifest.json`.
+    ///
+    /// With `--verify-determinism`, the hashes are compared to the manifest of the previous build
+    /// in the final dist dir first, failing the build if any output differs. The previous build is
+    /// kept in that case.
+    async fn write_output_manifest(&self) -> Result<()> {
+        let manifest = output_hashes(&self.cfg.staging_dist).await?;
+
+        if self.cfg.verify_determinism {
+            let reference_path = self.cfg.final_dist.join(OUTPUT_MANIFEST);
+            match fs::read(&reference_path).await {
+                Ok(reference) => {
+                    let reference: BTreeMap<String, String> = serde_json::from_slice(&reference)
+                        .context("error parsing reference output manifest")?;
+                    let differing = differing_outputs(&reference, &manifest);
+                    ensure!(
+                        differing.is_empty(),
+                        "build is not deterministic, these outputs differ from the previous \
+                         build: {}",
+                        differing.join(", ")
+                    );
+                    tracing::info!("build output is identical to the previous build");
+                }
+                Err(_) => tracing::warn!(
+                    "no output manifest of a previous build to verify against, this build is \
+                     recorded as reference"
+                ),
+            }
+        }
+
+        let manifest =
+            serde_json::to_vec_pretty(&manifest).context("error serializing output manifest")?;
+        fs::write(self.cfg.staging_dist.join(OUTPUT_MANIFEST), manifest)
+            .await
+            .context("error writing output manifest")
+    }
+
     /// Creates a "staging area" (dist/.stage) for storing intermediate build results.
     async fn prepare_staging_dist(&self) -> Result<()> {
         // Prepare staging area in which we will assemble the latest build
@@ -170,3 +236,100 @@ impl BuildSystem {
         Ok(())
     }
 }
+
+/// The name of the file in the dist dir recording the hashes of all outputs.
+const OUTPUT_MANIFEST: &str = ".trunk-manifest.json";
+
+/// Compute the SHA-256 hashes of all files in the given dir, keyed by their relative path using
+/// `/` as separator.
+async fn output_hashes(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let mut hashes = BTreeMap::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(current) = dirs.pop() {
+        let mut entries = fs::read_dir(&current)
+            .await
+            .map(ReadDirStream::new)
+            .with_context(|| format!("error reading dir {:?}", current))?;
+        while let Some(entry) = entries.next().await {
+            let entry =
+                entry.with_context(|| format!("error reading contents of {:?}", current))?;
+            let path = entry.path();
+            let file_type = entry
+                .file_type()
+                .await
+                .with_context(|| format!("error reading metadata of {:?}", path))?;
+            if file_type.is_dir() {
+                dirs.push(path);
+                continue;
+            }
+            let rel_path = path
+                .strip_prefix(dir)
+                .context("output file outside of dist dir")?
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            if rel_path != OUTPUT_MANIFEST {
+                hashes.insert(rel_path, sha256(&path).await?);
+            }
+        }
+    }
+    Ok(hashes)
+}
+
+/// Find the outputs which were added, removed or changed compared to the reference.
+fn differing_outputs(
+    reference: &BTreeMap<String, String>,
+    outputs: &BTreeMap<String, String>,
+) -> Vec<String> {
+    let removed = reference.keys().filter(|path| !outputs.contains_key(*path));
+    let changed = outputs
+        .iter()
+        .filter(|(path, hash)| reference.get(*path) != Some(*hash))
+        .map(|(path, _)| path);
+    removed.chain(changed).cloned().collect()
+}
+
+/// A build of the application for multiple WASM targets at once.
+///
+/// Each target is driven by its own [`BuildSystem`], writing its output into a subdirectory of
+/// the dist dir named after the target triple. All builds run in parallel.
+pub struct MultiTargetBuild {
+    /// The build systems, one per target triple.
+    systems: Vec<(String, BuildSystem)>,
+}
+
+impl MultiTargetBuild {
+    /// Create a new instance, with one build per target configured in `cfg.targets`.
+    pub async fn new(cfg: Arc<RtcBuild>) -> Result<Self> {
+        let mut systems = Vec::with_capacity(cfg.targets.len());
+        for target in &cfg.targets {
+            let target_cfg = Arc::new(cfg.for_target(target)?);
+            systems.push((target.clone(), BuildSystem::new(target_cfg, None).await?));
+        }
+        Ok(Self { systems })
+    }
+
+    /// Build all targets in parallel, failing if any of the targets failed to build.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub async fn build(self) -> Result<()> {
+        let handles: Vec<_> = self
+            .systems
+            .into_iter()
+            .map(|(target, mut system)| tokio::spawn(async move { (target, system.build().await) }))
+            .collect();
+
+        // The errors are logged by each build without its target, so they are repeated here.
+        let mut failed = vec![];
+        for handle in handles {
+            let (target, res) = handle.await.context("error joining target build")?;
+            if let Err(err) = res {
+                failed.push(format!("{}: {:#}", target, err));
+            }
+        }
+        if !failed.is_empty() {
+            bail!("build failed for target(s):\n{}", failed.join("\n"));
+        }
+        Ok(())
+    }
+}
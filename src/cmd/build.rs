@@ -3,8 +3,9 @@ use std::path::PathBuf;
 use anyhow::Result;
 use clap::Args;
 
-use crate::build::BuildSystem;
+use crate::build::{BuildSystem, MultiTargetBuild};
 use crate::config::{ConfigOpts, ConfigOptsBuild};
+use crate::trace::{TraceRecorder, TRACE_FILE};
 
 /// Build the Rust WASM app and all of its assets.
 #[derive(Clone, Debug, Args)]
@@ -12,14 +13,29 @@ use crate::config::{ConfigOpts, ConfigOptsBuild};
 pub struct Build {
     #[clap(flatten)]
     pub build: ConfigOptsBuild,
+    /// Write the timings of the build stages to `trunk-trace.json` in the dist dir, in the trace
+    /// format of `chrome://tracing`
+    #[clap(long)]
+    pub timings: bool,
+    /// The recorder of the build stages, set up with the logging when `--timings` is given.
+    #[clap(skip)]
+    pub trace: Option<TraceRecorder>,
 }
 
 impl Build {
     #[tracing::instrument(level = "trace", skip(self, config))]
     pub async fn run(self, config: Option<PathBuf>) -> Result<()> {
         let cfg = ConfigOpts::rtc_build(self.build, config)?;
-        let mut system = BuildSystem::new(cfg, None).await?;
-        system.build().await?;
+        let final_dist = cfg.final_dist.clone();
+        if cfg.targets.is_empty() {
+            let mut system = BuildSystem::new(cfg, None).await?;
+            system.build().await?;
+        } else {
+            MultiTargetBuild::new(cfg).await?.build().await?;
+        }
+        if let Some(trace) = &self.trace {
+            trace.write(&final_dist.join(TRACE_FILE)).await?;
+        }
         Ok(())
     }
 }
@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
 
 use anyhow::{ensure, Context, Result};
@@ -6,8 +6,8 @@ use clap::Args;
 use tokio::process::Command;
 
 use crate::common::remove_dir_all;
-use crate::config::{ConfigOpts, ConfigOptsClean};
-use crate::tools::cache_dir;
+use crate::config::{ConfigOpts, ConfigOptsClean, SASS_COMPILE_CACHE_DIR, SASS_WATCH_DIR};
+use crate::tools::{cache_dir, Application};
 
 /// Clean output artifacts.
 #[derive(Args)]
@@ -19,13 +19,25 @@ pub struct Clean {
     ///
     /// These tools are cached in a platform dependent "projects" dir. Removing them will cause
     /// them to be downloaded by Trunk next time they are needed.
-    #[clap(short, long)]
+    #[clap(short, long, alias = "all")]
     pub tools: bool,
+    /// Only clean the cached tools, keeping the output artifacts
+    #[clap(long, conflicts_with = "tools")]
+    pub cache: bool,
+    /// Only clean the cached versions of the given tool, one of `sass`, `wasm-bindgen`,
+    /// `wasm-opt`, `wasm-tools` or `wizer`
+    #[clap(long, requires = "cache")]
+    pub app: Option<String>,
 }
 
 impl Clean {
     #[tracing::instrument(level = "trace", skip(self, config))]
     pub async fn run(self, config: Option<PathBuf>) -> Result<()> {
+        let app: Option<Application> = self.app.as_deref().map(str::parse).transpose()?;
+        if self.cache {
+            return clean_cache(app).await;
+        }
+
         let cfg = ConfigOpts::rtc_clean(self.clean, config)?;
         let _ = remove_dir_all(cfg.dist.clone()).await;
         if cfg.cargo {
@@ -43,10 +55,80 @@ impl Clean {
             );
         }
         if self.tools {
-            tracing::debug!("cleaning trunk tools cache dir");
-            let path = cache_dir().await.context("error getting cache dir path")?;
-            remove_dir_all(path).await?;
+            clean_cache(None).await?;
         }
         Ok(())
     }
 }
+
+/// Remove the cached tools, or only the versions of the given tool, printing the removed paths
+/// and the freed size.
+async fn clean_cache(app: Option<Application>) -> Result<()> {
+    tracing::debug!("cleaning trunk tools cache dir");
+    let cache_dir = cache_dir().await.context("error getting cache dir path")?;
+    // Each tool version is installed to a `{name}-{version}` dir, next to the temporary files of
+    // running downloads. The caches of the sass pipeline share the prefix, but are not versions of
+    // the tool, so they are only removed along with all tools.
+    let prefix = app.map(|app| format!("{}-", app.name()));
+    let mut entries = tokio::fs::read_dir(&cache_dir)
+        .await
+        .context("failed reading cache directory")?;
+    let mut freed = 0;
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .context("failed reading cache directory entry")?
+    {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let skip = match &prefix {
+            Some(prefix) => {
+                !name.starts_with(prefix.as_str())
+                    || [SASS_COMPILE_CACHE_DIR, SASS_WATCH_DIR].contains(&name.as_str())
+            }
+            None => false,
+        };
+        if skip {
+            continue;
+        }
+        let path = entry.path();
+        let size = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || disk_usage(&path)
+        })
+        .await
+        .context("error awaiting spawned disk usage call")??;
+        if entry
+            .file_type()
+            .await
+            .context("failed reading cache directory entry")?
+            .is_dir()
+        {
+            remove_dir_all(path.clone()).await?;
+        } else {
+            tokio::fs::remove_file(&path)
+                .await
+                .with_context(|| format!("error removing {}", path.display()))?;
+        }
+        println!("removed {}", path.display());
+        freed += size;
+    }
+    println!("freed {:.1} MiB", freed as f64 / (1024.0 * 1024.0));
+    Ok(())
+}
+
+/// The total size of the files at the given path, including all subdirectories.
+fn disk_usage(path: &Path) -> Result<u64> {
+    let metadata = std::fs::symlink_metadata(path)
+        .with_context(|| format!("error reading metadata of {}", path.display()))?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)
+        .with_context(|| format!("error reading directory {}", path.display()))?
+    {
+        let entry = entry.with_context(|| format!("error reading directory {}", path.display()))?;
+        size += disk_usage(&entry.path())?;
+    }
+    Ok(size)
+}
@@ -1,5 +1,8 @@
 pub mod build;
 pub mod clean;
 pub mod config;
+pub mod optimize;
+pub mod sass;
 pub mod serve;
+pub mod tools;
 pub mod watch;
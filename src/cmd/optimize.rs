@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+
+use crate::common;
+use crate::config::ConfigOpts;
+use crate::tools::{self, Application};
+
+/// Optimize a WASM file with the wasm-opt version managed by Trunk.
+#[derive(Clone, Debug, Args)]
+#[clap(name = "optimize")]
+pub struct Optimize {
+    /// The WASM file to optimize
+    #[clap(parse(from_os_str))]
+    input: PathBuf,
+    /// The file to write the optimized WASM to [default: the input file]
+    #[clap(short, long, parse(from_os_str))]
+    output: Option<PathBuf>,
+    /// The optimization level, one of `1`, `2`, `3`, `4`, `s` or `z`, optionally prefixed with
+    /// `O` [default: wasm-opt's default level]
+    #[clap(short, long)]
+    level: Option<String>,
+}
+
+impl Optimize {
+    #[tracing::instrument(level = "trace", skip(self, config))]
+    pub async fn run(self, config: Option<PathBuf>) -> Result<()> {
+        let arg_level = match self
+            .level
+            .as_deref()
+            .map(|level| level.strip_prefix('O').unwrap_or(level))
+        {
+            None => "-O".to_owned(),
+            Some(level @ ("1" | "2" | "3" | "4" | "s" | "z")) => format!("-O{}", level),
+            Some(_) => bail!(
+                "unknown wasm-opt level `{}`",
+                self.level.as_deref().unwrap_or_default()
+            ),
+        };
+
+        // Use the wasm-opt version configured for builds.
+        let cfg = ConfigOpts::tools(config)?;
+        let wasm_opt = tools::get(Application::WasmOpt, cfg.wasm_opt.as_deref()).await?;
+
+        let output = self.output.as_ref().unwrap_or(&self.input);
+        let arg_output = format!("--output={}", output.display());
+        let arg_input = self.input.to_string_lossy();
+        let before = tokio::fs::metadata(&self.input)
+            .await
+            .with_context(|| format!("error reading metadata of {:?}", self.input))?
+            .len();
+
+        tracing::info!("calling wasm-opt");
+        common::run_command(
+            Application::WasmOpt.name(),
+            &wasm_opt,
+            &[arg_output.as_str(), &arg_input, &arg_level],
+        )
+        .await?;
+
+        let after = tokio::fs::metadata(output)
+            .await
+            .with_context(|| format!("error reading metadata of {:?}", output))?
+            .len();
+        tracing::info!(
+            "optimized {:?} from {} to {} bytes",
+            self.input,
+            before,
+            after
+        );
+        Ok(())
+    }
+}
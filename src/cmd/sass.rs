@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context, Result};
+use clap::{Args, Subcommand};
+use tokio::process::Command;
+
+use crate::config::ConfigOpts;
+use crate::tools::{self, Application};
+
+/// Use the sass version managed by Trunk.
+#[derive(Clone, Debug, Args)]
+#[clap(name = "sass")]
+pub struct Sass {
+    #[clap(subcommand)]
+    action: SassSubcommands,
+}
+
+impl Sass {
+    #[tracing::instrument(level = "trace", skip(self, config))]
+    pub async fn run(self, config: Option<PathBuf>) -> Result<()> {
+        match self.action {
+            SassSubcommands::Repl => repl(config).await,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum SassSubcommands {
+    /// Start the interactive shell of sass for evaluating sass expressions.
+    Repl,
+}
+
+/// Run `sass --interactive`, attached to the terminal.
+async fn repl(config: Option<PathBuf>) -> Result<()> {
+    // Use the sass version configured for builds.
+    let cfg = ConfigOpts::tools(config)?;
+    let sass = tools::get(Application::Sass, cfg.sass.as_deref()).await?;
+
+    // The standard streams are inherited by default.
+    let status = Command::new(&sass)
+        .arg("--interactive")
+        .status()
+        .await
+        .context("error spawning sass")?;
+    ensure!(status.success(), "sass exited with {}", status);
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context, Result};
+use clap::{Args, Subcommand};
+use serde::Serialize;
+
+use crate::common;
+use crate::config::{ConfigOpts, ConfigOptsTools};
+use crate::tools::{self, Application};
+
+/// Manage the tools downloaded by Trunk.
+#[derive(Clone, Debug, Args)]
+#[clap(name = "tools")]
+pub struct Tools {
+    #[clap(subcommand)]
+    action: ToolsSubcommands,
+}
+
+impl Tools {
+    #[tracing::instrument(level = "trace", skip(self, config))]
+    pub async fn run(self, config: Option<PathBuf>) -> Result<()> {
+        match self.action {
+            ToolsSubcommands::Hash(inner) => inner.run().await,
+            ToolsSubcommands::Update(inner) => inner.run(config).await,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Subcommand)]
+enum ToolsSubcommands {
+    /// Print the SHA-256 digest and path of cached tool binaries.
+    Hash(Hash),
+    /// Download the latest versions of all tools.
+    Update(Update),
+}
+
+/// Print the SHA-256 digest and path of cached tool binaries.
+///
+/// The output uses the format of `sha256sum`, so it can be cross-checked with the checksums
+/// published by the tool's maintainers.
+#[derive(Clone, Debug, Args)]
+struct Hash {
+    /// The tool to hash, one of `sass`, `wasm-bindgen`, `wasm-opt`, `wasm-tools` or `wizer`
+    #[clap(required_unless_present = "all", conflicts_with = "all")]
+    app: Option<String>,
+    /// The version of the tool to hash [default: the version Trunk downloads by default]
+    #[clap(long, conflicts_with = "all")]
+    version: Option<String>,
+    /// Hash all cached versions of all tools
+    #[clap(long)]
+    all: bool,
+}
+
+impl Hash {
+    async fn run(self) -> Result<()> {
+        let installations = match self.app {
+            Some(app) => {
+                let app: Application = app.parse()?;
+                let version = self
+                    .version
+                    .as_deref()
+                    .unwrap_or_else(|| app.default_version());
+                let installation = tools::cached(app)
+                    .await?
+                    .into_iter()
+                    .find(|(cached_version, _)| cached_version == version)
+                    .with_context(|| format!("{} {} is not cached", app.name(), version))?;
+                vec![installation]
+            }
+            None => {
+                let mut installations = vec![];
+                for app in Application::ALL {
+                    installations.extend(tools::cached(app).await?);
+                }
+                ensure!(!installations.is_empty(), "no tools are cached");
+                installations
+            }
+        };
+
+        for (_, path) in installations {
+            let hash = common::sha256(&path).await?;
+            println!("{}  {}", hash, path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Download the latest versions of all tools.
+///
+/// The configured versions are not changed, the printed versions can be set in the `[tools]`
+/// section of `Trunk.toml`.
+#[derive(Clone, Debug, Args)]
+struct Update {
+    /// Only print the configured and the latest versions, without downloading anything
+    #[clap(long)]
+    dry_run: bool,
+    /// Print the versions as JSON
+    #[clap(long, requires = "dry-run")]
+    json: bool,
+}
+
+/// A tool whose configured version may differ from its latest version.
+#[derive(Serialize)]
+struct ToolUpdate {
+    /// The name of the tool.
+    app: String,
+    /// The configured version, or the version Trunk downloads by default.
+    current: String,
+    /// The version of the latest release.
+    latest: String,
+    /// The URL the latest version is downloaded from.
+    url: String,
+}
+
+impl Update {
+    async fn run(self, config: Option<PathBuf>) -> Result<()> {
+        let tools = ConfigOpts::tools(config)?;
+        let mut updates = vec![];
+        for app in Application::ALL {
+            let latest = tools::latest_version(app).await?;
+            updates.push(ToolUpdate {
+                app: app.name().to_owned(),
+                current: configured_version(&tools, app)
+                    .unwrap_or_else(|| app.default_version())
+                    .to_owned(),
+                url: app.url(&latest)?,
+                latest,
+            });
+        }
+
+        if self.json {
+            let json =
+                serde_json::to_string_pretty(&updates).context("error serializing updates")?;
+            println!("{}", json);
+        } else {
+            println!("{:<14}{:<16}{:<16}url", "tool", "current", "latest");
+            for update in &updates {
+                println!(
+                    "{:<14}{:<16}{:<16}{}",
+                    update.app,
+                    update.current,
+                    update.latest,
+                    if update.current == update.latest {
+                        "up to date"
+                    } else {
+                        &update.url
+                    }
+                );
+            }
+        }
+        if self.dry_run {
+            return Ok(());
+        }
+
+        let outdated: Vec<_> = updates
+            .iter()
+            .filter(|update| update.current != update.latest)
+            .collect();
+        let apps = outdated
+            .iter()
+            .map(|update| Ok((update.app.parse()?, Some(update.latest.as_str()))))
+            .collect::<Result<Vec<_>>>()?;
+        tools::get_all(&apps).await?;
+        for update in outdated {
+            println!(
+                "downloaded {} {}, set it in the `[tools]` section of `Trunk.toml` to use it",
+                update.app, update.latest
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The version of the given tool set in the `[tools]` section of `Trunk.toml`, if any.
+fn configured_version(tools: &ConfigOptsTools, app: Application) -> Option<&str> {
+    match app {
+        Application::Sass => tools.sass.as_deref(),
+        Application::WasmBindgen => tools.wasm_bindgen.as_deref(),
+        Application::WasmOpt => tools.wasm_opt.as_deref(),
+        Application::WasmTools => tools.wasm_tools.as_deref(),
+        Application::Wizer => tools.wizer.as_deref(),
+    }
+}
@@ -10,7 +10,9 @@ use std::process::Stdio;
 use anyhow::{anyhow, bail, Context, Result};
 use console::Emoji;
 use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
 use tokio::fs;
+use tokio::io::AsyncReadExt;
 use tokio::process::Command;
 
 pub static BUILDING: Emoji<'_, '_> = Emoji("📦", "");
@@ -71,6 +73,27 @@ pub async fn remove_dir_all(from_dir: PathBuf) -> Result<()> {
     .context("error awaiting spawned remove dir call")?
 }
 
+/// Compute the hex encoded SHA-256 digest of a file, reading it in chunks.
+pub async fn sha256(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)
+        .await
+        .with_context(|| format!("failed opening {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let read = file
+            .read(&mut buf)
+            .await
+            .with_context(|| format!("failed reading {}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
 /// Checks if path exists.
 pub async fn path_exists(path: impl AsRef<Path>) -> Result<bool> {
     fs::metadata(path.as_ref())
@@ -15,10 +15,22 @@ mod rt;
 pub const DIST_DIR: &str = "dist";
 /// The name of the directory used to stage build artifacts during an active build.
 pub const STAGE_DIR: &str = ".stage";
+/// The name of the directory in Trunk's cache dir keeping the sass output between builds in watch
+/// mode.
+pub const SASS_WATCH_DIR: &str = "sass-watch";
+/// The name of the directory in Trunk's cache dir caching sass compilation results by the contents
+/// of the stylesheets.
+pub const SASS_COMPILE_CACHE_DIR: &str = "sass-cache";
+/// The name of the directory in Trunk's cache dir collecting the wasm-opt debug output of the
+/// last build of each dist dir.
+pub const WASM_OPT_DEBUG_DIR: &str = "debug";
+/// The WASM target triple cargo builds for, unless configured otherwise.
+pub const DEFAULT_CARGO_TARGET: &str = "wasm32-unknown-unknown";
 
 pub use manifest::CargoMetadata;
 pub use models::{
     ConfigOpts, ConfigOptsBuild, ConfigOptsClean, ConfigOptsHook, ConfigOptsProxy, ConfigOptsServe,
-    ConfigOptsTools, ConfigOptsWatch,
+    ConfigOptsTools, ConfigOptsToolsDownloadUrls, ConfigOptsToolsProxy, ConfigOptsWatch,
+    SassCharset, SassInputSyntax, WasmOptDiagnostics,
 };
 pub use rt::{Features, RtcBuild, RtcClean, RtcServe, RtcWatch};
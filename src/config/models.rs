@@ -6,12 +6,12 @@ use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use axum::http::Uri;
-use clap::Args;
+use clap::{Args, ValueEnum};
 use serde::{Deserialize, Deserializer};
 
 use crate::common::parse_public_url;
 use crate::config::{RtcBuild, RtcClean, RtcServe, RtcWatch};
-use crate::pipelines::PipelineStage;
+use crate::pipelines::{PipelineStage, WasmOptConfig};
 
 /// Config options for the build system.
 #[derive(Clone, Debug, Default, Deserialize, Args)]
@@ -23,8 +23,16 @@ pub struct ConfigOptsBuild {
     #[clap(long)]
     #[serde(default)]
     pub release: bool,
+    /// Skip wasm-opt, regardless of the configured optimization levels [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub no_opt: bool,
+    /// Build with the given cargo profile, like `production`, instead of `dev` or `release`
+    /// [default: None]
+    #[clap(long)]
+    pub cargo_profile: Option<String>,
     /// The output dir for all final assets [default: dist]
-    #[clap(short, long, parse(from_os_str))]
+    #[clap(short, long, alias = "output-dir", parse(from_os_str))]
     pub dist: Option<PathBuf>,
     /// The public URL from which assets are to be served [default: /]
     #[clap(long, parse(from_str=parse_public_url))]
@@ -44,6 +52,124 @@ pub struct ConfigOptsBuild {
     /// Whether to include hash values in the output file names [default: true]
     #[clap(long)]
     pub filehash: Option<bool>,
+    /// Build for each of the given WASM target triples, writing the output of each target into a
+    /// subdirectory of the dist dir named after the target [default: []]
+    ///
+    /// When empty, a single build for `wasm32-unknown-unknown` is performed into the dist dir.
+    /// Only used by `trunk build`.
+    #[clap(long, value_name = "triple")]
+    pub targets: Option<Vec<String>>,
+    /// Silence sass deprecation warnings coming from dependencies, like third-party CSS
+    /// frameworks [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub sass_quiet_deps: bool,
+    /// Have sass print all deprecation warnings, even when they are repetitive [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub sass_verbose: bool,
+    /// Have sass only use ASCII characters in its error and status messages, which does not affect
+    /// the generated CSS [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub sass_no_unicode: bool,
+    /// Have sass emit a stylesheet showing the error in the browser when compilation fails,
+    /// instead of failing the build [default: true for `trunk serve` outside of release mode]
+    #[clap(long)]
+    pub sass_error_css: Option<bool>,
+    /// Show sass errors in an overlay covering the page, formatted like the error overlay of
+    /// webpack, instead of failing the build [default: true for `trunk serve` outside of release
+    /// mode]
+    #[clap(long)]
+    pub sass_error_overlay: Option<bool>,
+    /// The maximum number of wasm-opt processes running in parallel [default: number of logical
+    /// CPUs]
+    #[clap(long, value_name = "n")]
+    pub wasm_opt_parallelism: Option<usize>,
+    /// Keep the output of wasm-opt even when it is larger than its input, for optimizations
+    /// focusing on speed rather than size [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub wasm_opt_always_use_output: bool,
+    /// Write the beginning of the AST of each wasm-opt output, or of the input wasm-opt failed to
+    /// optimize, to a file in Trunk's cache dir, for diagnosing invalid wasm-opt output
+    /// [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub wasm_opt_debug_output: bool,
+    /// Diagnostics printed by wasm-opt, for troubleshooting its optimizations.
+    ///
+    /// These values can only be provided via config file.
+    #[clap(skip)]
+    #[serde(default)]
+    pub wasm_opt_diagnostics: Option<WasmOptDiagnostics>,
+    /// Have wasm-bindgen generate extra assertions and better error messages in the code at the
+    /// boundary between JS and WASM [default: true, false in release mode]
+    #[clap(long)]
+    pub wasm_bindgen_debug: Option<bool>,
+    /// Generate a `package.json` for the wasm-bindgen output of the main application, for
+    /// publishing it as npm package [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub wasm_bindgen_generate_package_json: bool,
+    /// The file name stem of the wasm-bindgen output of the main application [default: the name
+    /// of the binary]
+    #[clap(long, value_name = "name")]
+    pub wasm_bindgen_out_name: Option<String>,
+    /// Fail the build if any output differs from the previous build, recorded in the
+    /// `.trunk-manifest.json` of the dist dir [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub verify_determinism: bool,
+    /// Log the 20 largest items of each optimized WASM file with `twiggy top`, which has to be
+    /// installed [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub wasm_size_profile: bool,
+    /// Skip checking that the WASM binaries built by cargo are well-formed before running
+    /// wasm-bindgen on them [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub skip_wasm_validation: bool,
+    /// Allow the use of experimental features, whose behavior may change in future versions
+    /// [default: false]
+    #[clap(long)]
+    #[serde(default)]
+    pub experimental_features: bool,
+    /// Whether sass emits a `@charset` declaration or BOM for non-ASCII CSS [default: auto]
+    #[clap(long, value_enum, value_name = "charset")]
+    pub sass_charset: Option<SassCharset>,
+    /// The syntax sass parses the stylesheets with, instead of the one of their file extension
+    /// [default: auto]
+    #[clap(long, value_enum, value_name = "syntax")]
+    pub sass_input_syntax: Option<SassInputSyntax>,
+    /// Silence the given sass deprecation warning, can be specified multiple times
+    #[clap(long = "sass-silence-deprecation", value_name = "deprecation")]
+    pub sass_silence_deprecations: Option<Vec<String>>,
+    /// Additional dirs sass resolves imports from, can be specified multiple times
+    #[clap(long = "sass-import-path", value_name = "path")]
+    pub sass_import_paths: Option<Vec<PathBuf>>,
+    /// Have sass resolve imports from the `sass`, `scss`, `styles` and `stylesheets` dirs next to
+    /// the source HTML file, if they exist [default: true]
+    #[clap(long)]
+    pub sass_auto_load_paths: Option<bool>,
+    /// Place the compiled CSS at the same path relative to the dist dir as the stylesheet relative
+    /// to the source HTML file, instead of at the root of the dist dir [default: true]
+    #[clap(long)]
+    pub sass_mirror_structure: Option<bool>,
+    /// Run the compiled sass/scss stylesheets through PostCSS [default: true if the PostCSS config
+    /// file exists]
+    #[clap(long)]
+    pub postcss: Option<bool>,
+    /// The PostCSS config file, without which the autoprefixer plugin is used [default:
+    /// `postcss.config.js` next to the source HTML file]
+    #[clap(long, value_name = "path")]
+    pub postcss_config: Option<PathBuf>,
+    /// The `node_modules` dir sass resolves `pkg:` and package imports from [default:
+    /// `node_modules` next to the source HTML file, if it exists]
+    #[clap(long, alias = "sass-node-modules-path", value_name = "path")]
+    #[serde(alias = "sass_node_modules_path")]
+    pub sass_pkg_importer: Option<PathBuf>,
     /// Optional pattern for the app loader script [default: None]
     ///
     /// Patterns should include the sequences `{base}`, `{wasm}`, and `{js}` in order to
@@ -80,6 +206,51 @@ pub struct ConfigOptsBuild {
     ///
     /// These values can only be provided via config file.
     pub pattern_params: Option<HashMap<String, String>>,
+    /// The wasm-opt configurations by cargo profile name, used for builds with the profile
+    /// instead of the default configuration of the profile. The `data-wasm-opt*` attributes of
+    /// an asset take precedence over them.
+    ///
+    /// These values can only be provided via config file.
+    #[clap(skip)]
+    #[serde(default)]
+    pub wasm_opt_profiles: Option<HashMap<String, WasmOptConfig>>,
+}
+
+/// Config options for the diagnostics printed by wasm-opt.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct WasmOptDiagnostics {
+    /// Log the WASM proposals wasm-opt detects as used by each input, at the debug level.
+    #[serde(default)]
+    pub print_features: bool,
+}
+
+/// The `@charset` handling of sass.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SassCharset {
+    /// Keep the default behavior of the used sass version.
+    #[default]
+    Auto,
+    /// Emit a `@charset` declaration or BOM when the CSS contains non-ASCII characters.
+    Always,
+    /// Never emit a `@charset` declaration or BOM.
+    Never,
+}
+
+/// The syntax sass parses stylesheets with.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum SassInputSyntax {
+    /// The syntax of the file extension: `.sass` for the indented syntax, `.css` for plain CSS and
+    /// SCSS for any other extension.
+    #[default]
+    Auto,
+    /// SCSS, the superset of CSS adding variables, nesting, mixins and more.
+    Scss,
+    /// The indented syntax.
+    Sass,
+    /// Plain CSS.
+    Css,
 }
 
 /// Config options for the watch system.
@@ -150,6 +321,56 @@ pub struct ConfigOptsTools {
     pub wasm_bindgen: Option<String>,
     /// Version of `wasm-opt` to use.
     pub wasm_opt: Option<String>,
+    /// Version of `wasm-tools` to use.
+    pub wasm_tools: Option<String>,
+    /// Version of `wizer` to use.
+    pub wizer: Option<String>,
+    /// The proxy to download the tools through, instead of the one of the `HTTP_PROXY` and
+    /// `HTTPS_PROXY` env vars.
+    pub proxy: Option<ConfigOptsToolsProxy>,
+    /// The URLs to download the tools from instead of their GitHub releases.
+    pub download_urls: Option<ConfigOptsToolsDownloadUrls>,
+    /// Only use system installed and previously downloaded tools, failing if one is missing
+    /// instead of downloading it.
+    #[serde(default)]
+    pub offline: bool,
+}
+
+/// Config options for the URLs tools are downloaded from, like the ones of a mirror, where
+/// `{version}` is replaced by the version to download.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigOptsToolsDownloadUrls {
+    /// The URL to download `dart-sass` from.
+    pub sass: Option<String>,
+    /// The URL to download `wasm-bindgen` from.
+    pub wasm_bindgen: Option<String>,
+    /// The URL to download `wasm-opt` from.
+    pub wasm_opt: Option<String>,
+    /// The URL to download `wasm-tools` from.
+    pub wasm_tools: Option<String>,
+    /// The URL to download `wizer` from.
+    pub wizer: Option<String>,
+}
+
+/// Config options for the proxy tools are downloaded through.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigOptsToolsProxy {
+    /// The URL of the proxy for HTTP requests.
+    pub http_url: Option<String>,
+    /// The URL of the proxy for HTTPS requests.
+    pub https_url: Option<String>,
+    /// A comma separated list of hosts to connect to directly, including their subdomains, or `*`
+    /// for all hosts.
+    pub no_proxy: Option<String>,
+}
+
+impl ConfigOptsToolsProxy {
+    /// Whether any proxy is configured.
+    pub fn is_configured(&self) -> bool {
+        self.http_url.is_some() || self.https_url.is_some()
+    }
 }
 
 /// Config options for building proxies.
@@ -283,19 +504,82 @@ impl ConfigOpts {
         Self::file_and_env_layers(config)
     }
 
+    /// Return the `[tools]` configuration based on config file & environment variables.
+    ///
+    /// The other sections of the config file are neither read nor validated, so commands which
+    /// only use tools work even if those sections are invalid, like for a missing build target.
+    pub fn tools(config: Option<PathBuf>) -> Result<ConfigOptsTools> {
+        /// The config file, ignoring all sections but `[tools]`.
+        #[derive(Deserialize)]
+        struct ToolsOnly {
+            tools: Option<ConfigOptsTools>,
+        }
+
+        let trunk_toml_path = config.unwrap_or_else(|| "Trunk.toml".into());
+        let toml_tools = if trunk_toml_path.exists() {
+            let cfg_bytes = std::fs::read(&trunk_toml_path).context("error reading config file")?;
+            let cfg: ToolsOnly = toml::from_slice(&cfg_bytes)
+                .context("error reading config file contents as TOML data")?;
+            cfg.tools
+        } else {
+            None
+        };
+        let env_tools = Self::tools_from_env().context("error reading trunk env var config")?;
+        let cfg = Self::merge(
+            Self {
+                tools: toml_tools,
+                ..Default::default()
+            },
+            Self {
+                tools: Some(env_tools),
+                ..Default::default()
+            },
+        );
+        Ok(cfg.tools.unwrap_or_default())
+    }
+
     fn cli_opts_layer_build(cli: ConfigOptsBuild, cfg_base: Self) -> Self {
         let opts = ConfigOptsBuild {
             target: cli.target,
             release: cli.release,
+            no_opt: cli.no_opt,
+            cargo_profile: cli.cargo_profile,
             dist: cli.dist,
             public_url: cli.public_url,
             no_default_features: cli.no_default_features,
             all_features: cli.all_features,
             features: cli.features,
             filehash: cli.filehash,
+            targets: cli.targets,
+            sass_quiet_deps: cli.sass_quiet_deps,
+            sass_verbose: cli.sass_verbose,
+            sass_no_unicode: cli.sass_no_unicode,
+            sass_error_css: cli.sass_error_css,
+            sass_error_overlay: cli.sass_error_overlay,
+            wasm_opt_parallelism: cli.wasm_opt_parallelism,
+            wasm_opt_always_use_output: cli.wasm_opt_always_use_output,
+            wasm_opt_debug_output: cli.wasm_opt_debug_output,
+            wasm_opt_diagnostics: cli.wasm_opt_diagnostics,
+            wasm_bindgen_debug: cli.wasm_bindgen_debug,
+            wasm_bindgen_generate_package_json: cli.wasm_bindgen_generate_package_json,
+            wasm_bindgen_out_name: cli.wasm_bindgen_out_name,
+            verify_determinism: cli.verify_determinism,
+            wasm_size_profile: cli.wasm_size_profile,
+            skip_wasm_validation: cli.skip_wasm_validation,
+            experimental_features: cli.experimental_features,
+            sass_charset: cli.sass_charset,
+            sass_input_syntax: cli.sass_input_syntax,
+            sass_silence_deprecations: cli.sass_silence_deprecations,
+            sass_import_paths: cli.sass_import_paths,
+            sass_auto_load_paths: cli.sass_auto_load_paths,
+            sass_mirror_structure: cli.sass_mirror_structure,
+            postcss: cli.postcss,
+            postcss_config: cli.postcss_config,
+            sass_pkg_importer: cli.sass_pkg_importer,
             pattern_script: cli.pattern_script,
             pattern_preload: cli.pattern_preload,
             pattern_params: cli.pattern_params,
+            wasm_opt_profiles: cli.wasm_opt_profiles,
         };
         let cfg_build = ConfigOpts {
             build: Some(opts),
@@ -411,6 +695,21 @@ impl ConfigOpts {
                         *dist = parent.join(&dist);
                     }
                 }
+                for import_path in build.sass_import_paths.iter_mut().flatten() {
+                    if !import_path.is_absolute() {
+                        *import_path = parent.join(&import_path);
+                    }
+                }
+                if let Some(pkg_importer) = build.sass_pkg_importer.as_mut() {
+                    if !pkg_importer.is_absolute() {
+                        *pkg_importer = parent.join(&pkg_importer);
+                    }
+                }
+                if let Some(postcss_config) = build.postcss_config.as_mut() {
+                    if !postcss_config.is_absolute() {
+                        *postcss_config = parent.join(&postcss_config);
+                    }
+                }
             }
             if let Some(watch) = cfg.watch.as_mut() {
                 if let Some(watch_paths) = watch.watch.as_mut() {
@@ -458,12 +757,20 @@ impl ConfigOpts {
             watch: Some(envy::prefixed("TRUNK_WATCH_").from_env()?),
             serve: Some(envy::prefixed("TRUNK_SERVE_").from_env()?),
             clean: Some(envy::prefixed("TRUNK_CLEAN_").from_env()?),
-            tools: Some(envy::prefixed("TRUNK_TOOLS_").from_env()?),
+            tools: Some(Self::tools_from_env()?),
             proxy: None,
             hooks: None,
         })
     }
 
+    fn tools_from_env() -> Result<ConfigOptsTools> {
+        Ok(ConfigOptsTools {
+            proxy: Some(envy::prefixed("TRUNK_TOOLS_PROXY_").from_env()?),
+            download_urls: Some(envy::prefixed("TRUNK_TOOLS_DOWNLOAD_URLS_").from_env()?),
+            ..envy::prefixed("TRUNK_TOOLS_").from_env()?
+        })
+    }
+
     /// Merge the given layers, where the `greater` layer takes precedence.
     fn merge(mut lesser: Self, mut greater: Self) -> Self {
         greater.build = match (lesser.build.take(), greater.build.take()) {
@@ -474,13 +781,76 @@ impl ConfigOpts {
                 g.dist = g.dist.or(l.dist);
                 g.public_url = g.public_url.or(l.public_url);
                 g.filehash = g.filehash.or(l.filehash);
+                g.targets = g.targets.or(l.targets);
+                g.cargo_profile = g.cargo_profile.or(l.cargo_profile);
                 // NOTE: this can not be disabled in the cascade.
                 if l.release {
                     g.release = true;
                 }
+                // NOTE: this can not be disabled in the cascade.
+                if l.no_opt {
+                    g.no_opt = true;
+                }
+                // NOTE: this can not be disabled in the cascade.
+                if l.sass_quiet_deps {
+                    g.sass_quiet_deps = true;
+                }
+                // NOTE: this can not be disabled in the cascade.
+                if l.sass_verbose {
+                    g.sass_verbose = true;
+                }
+                // NOTE: this can not be disabled in the cascade.
+                if l.sass_no_unicode {
+                    g.sass_no_unicode = true;
+                }
+                g.sass_error_css = g.sass_error_css.or(l.sass_error_css);
+                g.sass_error_overlay = g.sass_error_overlay.or(l.sass_error_overlay);
+                g.wasm_opt_parallelism = g.wasm_opt_parallelism.or(l.wasm_opt_parallelism);
+                g.wasm_opt_diagnostics = g.wasm_opt_diagnostics.or(l.wasm_opt_diagnostics);
+                // NOTE: this can not be disabled in the cascade.
+                if l.wasm_opt_always_use_output {
+                    g.wasm_opt_always_use_output = true;
+                }
+                // NOTE: this can not be disabled in the cascade.
+                if l.wasm_opt_debug_output {
+                    g.wasm_opt_debug_output = true;
+                }
+                g.wasm_bindgen_debug = g.wasm_bindgen_debug.or(l.wasm_bindgen_debug);
+                // NOTE: this can not be disabled in the cascade.
+                if l.wasm_bindgen_generate_package_json {
+                    g.wasm_bindgen_generate_package_json = true;
+                }
+                g.wasm_bindgen_out_name = g.wasm_bindgen_out_name.or(l.wasm_bindgen_out_name);
+                // NOTE: this can not be disabled in the cascade.
+                if l.verify_determinism {
+                    g.verify_determinism = true;
+                }
+                // NOTE: this can not be disabled in the cascade.
+                if l.wasm_size_profile {
+                    g.wasm_size_profile = true;
+                }
+                // NOTE: this can not be disabled in the cascade.
+                if l.skip_wasm_validation {
+                    g.skip_wasm_validation = true;
+                }
+                // NOTE: this can not be disabled in the cascade.
+                if l.experimental_features {
+                    g.experimental_features = true;
+                }
+                g.sass_charset = g.sass_charset.or(l.sass_charset);
+                g.sass_input_syntax = g.sass_input_syntax.or(l.sass_input_syntax);
+                g.sass_silence_deprecations =
+                    g.sass_silence_deprecations.or(l.sass_silence_deprecations);
+                g.sass_import_paths = g.sass_import_paths.or(l.sass_import_paths);
+                g.sass_auto_load_paths = g.sass_auto_load_paths.or(l.sass_auto_load_paths);
+                g.sass_mirror_structure = g.sass_mirror_structure.or(l.sass_mirror_structure);
+                g.postcss = g.postcss.or(l.postcss);
+                g.postcss_config = g.postcss_config.or(l.postcss_config);
+                g.sass_pkg_importer = g.sass_pkg_importer.or(l.sass_pkg_importer);
                 g.pattern_preload = g.pattern_preload.or(l.pattern_preload);
                 g.pattern_script = g.pattern_script.or(l.pattern_script);
                 g.pattern_params = g.pattern_params.or(l.pattern_params);
+                g.wasm_opt_profiles = g.wasm_opt_profiles.or(l.wasm_opt_profiles);
                 Some(g)
             }
         };
@@ -520,6 +890,32 @@ impl ConfigOpts {
                 g.sass = g.sass.or(l.sass);
                 g.wasm_bindgen = g.wasm_bindgen.or(l.wasm_bindgen);
                 g.wasm_opt = g.wasm_opt.or(l.wasm_opt);
+                g.wasm_tools = g.wasm_tools.or(l.wasm_tools);
+                g.wizer = g.wizer.or(l.wizer);
+                // NOTE: this can not be disabled in the cascade.
+                if l.offline {
+                    g.offline = true;
+                }
+                g.proxy = match (l.proxy, g.proxy) {
+                    (Some(l), Some(mut g)) => {
+                        g.http_url = g.http_url.or(l.http_url);
+                        g.https_url = g.https_url.or(l.https_url);
+                        g.no_proxy = g.no_proxy.or(l.no_proxy);
+                        Some(g)
+                    }
+                    (l, g) => g.or(l),
+                };
+                g.download_urls = match (l.download_urls, g.download_urls) {
+                    (Some(l), Some(mut g)) => {
+                        g.sass = g.sass.or(l.sass);
+                        g.wasm_bindgen = g.wasm_bindgen.or(l.wasm_bindgen);
+                        g.wasm_opt = g.wasm_opt.or(l.wasm_opt);
+                        g.wasm_tools = g.wasm_tools.or(l.wasm_tools);
+                        g.wizer = g.wizer.or(l.wizer);
+                        Some(g)
+                    }
+                    (l, g) => g.or(l),
+                };
                 Some(g)
             }
         };
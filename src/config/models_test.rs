@@ -41,3 +41,124 @@ fn err_bad_trunk_toml_watch_ignore() {
     );
     assert_eq!(err.to_string(), expected_err);
 }
+
+#[test]
+fn trunk_toml_tools_proxy() {
+    let cwd = std::env::current_dir().expect("error getting cwd");
+    let path = cwd.join("tests").join("data").join("tools-proxy.toml");
+    let tools = ConfigOpts::full(Some(path))
+        .expect("error reading config")
+        .tools
+        .expect("expected tools config");
+    assert_eq!(tools.wasm_opt.as_deref(), Some("version_116"));
+    let proxy = tools.proxy.expect("expected tools proxy config");
+    assert!(proxy.is_configured());
+    assert_eq!(
+        proxy.http_url.as_deref(),
+        Some("http://proxy.example.com:3128")
+    );
+    assert_eq!(
+        proxy.https_url.as_deref(),
+        Some("http://proxy.example.com:3129")
+    );
+    assert_eq!(
+        proxy.no_proxy.as_deref(),
+        Some("localhost,.internal.example.com")
+    );
+}
+
+#[test]
+fn trunk_toml_tools_ignores_other_sections() {
+    let cwd = std::env::current_dir().expect("error getting cwd");
+    let path = cwd.join("tests").join("data").join("bad-build-target.toml");
+    ConfigOpts::full(Some(path.clone())).expect_err("expected config to err");
+    let tools = ConfigOpts::tools(Some(path)).expect("error reading tools config");
+    assert_eq!(tools.sass, None);
+
+    let path = cwd.join("tests").join("data").join("tools-proxy.toml");
+    let tools = ConfigOpts::tools(Some(path)).expect("error reading tools config");
+    assert_eq!(tools.wasm_opt.as_deref(), Some("version_116"));
+}
+
+#[test]
+fn trunk_toml_tools_download_urls() {
+    let cwd = std::env::current_dir().expect("error getting cwd");
+    let path = cwd
+        .join("tests")
+        .join("data")
+        .join("tools-download-urls.toml");
+    let urls = ConfigOpts::full(Some(path))
+        .expect("error reading config")
+        .tools
+        .and_then(|tools| tools.download_urls)
+        .expect("expected tools download URLs config");
+    assert_eq!(
+        urls.wasm_bindgen.as_deref(),
+        Some(
+            "https://artifactory.example.com/wasm-bindgen/{version}/\
+             wasm-bindgen-{version}-x86_64-unknown-linux-musl.tar.gz"
+        )
+    );
+    assert_eq!(urls.sass, None);
+}
+
+#[test]
+fn err_bad_tools_proxy() {
+    let err = toml::from_str::<ConfigOpts>("[tools.proxy]\nurl = \"http://proxy:3128\"\n")
+        .expect_err("expected unknown proxy field to err");
+    assert!(err.to_string().contains("unknown field `url`"));
+    let proxy: ConfigOptsToolsProxy =
+        toml::from_str("no_proxy = \"localhost\"").expect("error parsing proxy config");
+    assert!(!proxy.is_configured());
+}
+
+#[test]
+fn sass_node_pkg_importer_by_version() {
+    let dir = tempfile::tempdir().expect("error creating temp dir");
+    std::fs::write(dir.path().join("index.html"), "<html></html>").expect("error writing html");
+    std::fs::create_dir(dir.path().join("node_modules")).expect("error creating node_modules");
+    let rtc_build = |toml: &str| {
+        let path = dir.path().join("Trunk.toml");
+        std::fs::write(&path, format!("[build]\ntarget = \"index.html\"\n{}", toml))
+            .expect("error writing config");
+        ConfigOpts::rtc_build(Default::default(), Some(path)).expect("error reading config")
+    };
+
+    let cfg = rtc_build("");
+    assert!(cfg.sass_pkg_importer.is_some());
+    assert!(cfg.sass_node_pkg_importer);
+
+    // Older versions only get the auto-detected dir as load path.
+    let cfg = rtc_build("[tools]\nsass = \"1.69.5\"\n");
+    assert!(cfg.sass_pkg_importer.is_some());
+    assert!(!cfg.sass_node_pkg_importer);
+
+    // Unless it is configured explicitly.
+    let cfg = rtc_build("sass_pkg_importer = \"node_modules\"\n[tools]\nsass = \"1.69.5\"\n");
+    assert!(cfg.sass_node_pkg_importer);
+}
+
+#[test]
+fn sass_silence_deprecations_by_version() {
+    let dir = tempfile::tempdir().expect("error creating temp dir");
+    std::fs::write(dir.path().join("index.html"), "<html></html>").expect("error writing html");
+    let rtc_build = |toml: &str| {
+        let path = dir.path().join("Trunk.toml");
+        std::fs::write(&path, format!("[build]\ntarget = \"index.html\"\n{}", toml))
+            .expect("error writing config");
+        ConfigOpts::rtc_build(Default::default(), Some(path))
+    };
+
+    // The default sass version does not support `--silence-deprecation` yet.
+    let err = rtc_build("sass_silence_deprecations = [\"slash-div\"]\n")
+        .expect_err("expected config to err");
+    assert!(err.to_string().contains("requires sass 1.74.0 or newer"));
+    let err = rtc_build("sass_silence_deprecations = [\"import\"]\n[tools]\nsass = \"1.77.8\"\n")
+        .expect_err("expected config to err");
+    assert!(err.to_string().contains("requires sass 1.80.0 or newer"));
+
+    rtc_build("sass_silence_deprecations = [\"slash-div\"]\n[tools]\nsass = \"1.77.8\"\n")
+        .expect("error reading config");
+    rtc_build("sass_silence_deprecations = [\"import\"]\n[tools]\nsass = \"^1.70\"\n")
+        .expect("error reading config");
+}
@@ -8,8 +8,19 @@ use axum::http::Uri;
 
 use crate::config::{
     ConfigOptsBuild, ConfigOptsClean, ConfigOptsHook, ConfigOptsProxy, ConfigOptsServe,
-    ConfigOptsTools, ConfigOptsWatch,
+    ConfigOptsTools, ConfigOptsWatch, SassCharset, SassInputSyntax, WasmOptDiagnostics,
 };
+use crate::pipelines::{
+    SassDaemons, SassIncrementalMode, WasmOptConfig, WasmOptPool, SASS_DEPRECATIONS,
+};
+use crate::tools::Application;
+
+/// The dirs next to the source HTML file which sass resolves imports from by default, if they
+/// exist.
+const SASS_AUTO_LOAD_PATHS: &[&str] = &["sass", "scss", "styles", "stylesheets"];
+
+/// The first sass version supporting `--pkg-importer`.
+const SASS_PKG_IMPORTER_MIN_VERSION: &str = "1.71.0";
 
 /// Config options for the cargo build command
 #[derive(Clone, Debug)]
@@ -34,15 +45,87 @@ pub struct RtcBuild {
     pub target_parent: PathBuf,
     /// Build in release mode.
     pub release: bool,
+    /// Skip wasm-opt, regardless of the configured optimization levels.
+    pub no_opt: bool,
+    /// The cargo profile to build with, if not `dev` or `release`.
+    pub cargo_profile: Option<String>,
+    /// The wasm-opt configurations by cargo profile name.
+    pub wasm_opt_profiles: HashMap<String, WasmOptConfig>,
     /// The public URL from which assets are to be served.
     pub public_url: String,
     pub filehash: bool,
+    /// The WASM target triple passed to cargo.
+    pub cargo_target: String,
+    /// Whether `cargo_target` is one of the `targets` of a multi-target build.
+    ///
+    /// The target of such a build is not overridden by `data-bindgen-target="wasi-p2"`.
+    pub is_target_build: bool,
+    /// All WASM target triples to build for, each into its own subdirectory of the dist dir.
+    ///
+    /// When empty, only a single build for `cargo_target` is performed.
+    pub targets: Vec<String>,
     /// The directory where final build artifacts are placed after a successful build.
     pub final_dist: PathBuf,
     /// The directory used to stage build artifacts during an active build.
     pub staging_dist: PathBuf,
     /// The configuration of the features passed to cargo.
     pub cargo_features: Features,
+    /// Silence sass deprecation warnings coming from dependencies.
+    pub sass_quiet_deps: bool,
+    /// Have sass print all deprecation warnings, even repetitive ones.
+    pub sass_verbose: bool,
+    /// Have sass only use ASCII characters in its messages.
+    pub sass_no_unicode: bool,
+    /// Have sass emit a stylesheet showing the error when compilation fails.
+    pub sass_error_css: bool,
+    /// Show sass errors in an overlay covering the page when compilation fails.
+    pub sass_error_overlay: bool,
+    /// The pool limiting the number of wasm-opt processes running in parallel.
+    pub wasm_opt_pool: WasmOptPool,
+    /// Keep the output of wasm-opt even when it is larger than its input.
+    pub wasm_opt_always_use_output: bool,
+    /// Write the beginning of the AST of each wasm-opt output to a file in Trunk's cache dir.
+    pub wasm_opt_debug_output: bool,
+    /// The diagnostics printed by wasm-opt.
+    pub wasm_opt_diagnostics: WasmOptDiagnostics,
+    /// Have wasm-bindgen generate extra assertions for the code at the JS/WASM boundary.
+    pub wasm_bindgen_debug: bool,
+    /// Generate a `package.json` for the wasm-bindgen output of the main application.
+    pub wasm_bindgen_generate_package_json: bool,
+    /// The file name stem of the wasm-bindgen output of the main application, if not the name
+    /// of the binary.
+    pub wasm_bindgen_out_name: Option<String>,
+    /// Fail the build if any output differs from the previous build.
+    pub verify_determinism: bool,
+    /// Log the largest items of each optimized WASM file.
+    pub wasm_size_profile: bool,
+    /// Skip checking that the WASM binaries built by cargo are well-formed.
+    pub skip_wasm_validation: bool,
+    /// Allow the use of experimental features.
+    pub experimental_features: bool,
+    /// The `@charset` handling of sass.
+    pub sass_charset: SassCharset,
+    /// The syntax sass parses the stylesheets with.
+    pub sass_input_syntax: SassInputSyntax,
+    /// Whether sass compiles all stylesheets on every build, or only the out-of-date ones.
+    pub sass_incremental: SassIncrementalMode,
+    /// The sass processes kept running between watch builds.
+    pub sass_daemons: SassDaemons,
+    /// The sass deprecation warnings to silence.
+    pub sass_silence_deprecations: Vec<String>,
+    /// Additional dirs from which sass resolves imports.
+    pub sass_import_paths: Vec<PathBuf>,
+    /// Place the compiled CSS at the path of the stylesheet relative to the source HTML file.
+    pub sass_mirror_structure: bool,
+    /// Run the compiled sass/scss stylesheets through PostCSS.
+    pub postcss: bool,
+    /// The PostCSS config file, which may not exist.
+    pub postcss_config: PathBuf,
+    /// The `node_modules` dir from which sass resolves `pkg:` and package imports, if any.
+    pub sass_pkg_importer: Option<PathBuf>,
+    /// Whether sass' node package importer resolves `pkg:` imports. Otherwise, the
+    /// `sass_pkg_importer` dir only serves as load path for plain package imports.
+    pub sass_node_pkg_importer: bool,
     /// Configuration for automatic application download.
     pub tools: ConfigOptsTools,
     /// Build process hooks.
@@ -107,6 +190,92 @@ impl RtcBuild {
             "Cannot combine --all-features with --no-default-features and/or --features"
         );
 
+        let sass_silence_deprecations = opts.sass_silence_deprecations.unwrap_or_default();
+        // The configured sass version is checked unless it is a range, which may be satisfied by
+        // a newer system installed sass.
+        let sass_version = tools
+            .sass
+            .as_deref()
+            .unwrap_or_else(|| Application::Sass.default_version());
+        let check_sass_version = !crate::tools::is_version_range(sass_version);
+        for deprecation in &sass_silence_deprecations {
+            let since = SASS_DEPRECATIONS
+                .iter()
+                .find(|(name, _)| name == deprecation)
+                .map(|(_, since)| *since)
+                .with_context(|| {
+                    let names: Vec<_> = SASS_DEPRECATIONS.iter().map(|(name, _)| *name).collect();
+                    format!(
+                        "unknown sass deprecation `{}`, expected one of: {}",
+                        deprecation,
+                        names.join(", ")
+                    )
+                })?;
+            ensure!(
+                !check_sass_version
+                    || crate::tools::version_at_least(Application::Sass, Some(sass_version), since),
+                "silencing the sass deprecation `{}` requires sass {} or newer, but sass {} is \
+                 configured, please set a newer version with `sass` in the `[tools]` section of \
+                 `Trunk.toml`",
+                deprecation,
+                since,
+                sass_version
+            );
+        }
+
+        if let Some(out_name) = &opts.wasm_bindgen_out_name {
+            ensure!(
+                !out_name.is_empty()
+                    && out_name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+                "invalid wasm-bindgen out name `{}`, only ASCII letters, digits, `-` and `_` are \
+                 allowed",
+                out_name
+            );
+        }
+
+        // Shared partials are commonly kept in a dir of one of these names next to the source HTML
+        // file. The configured import paths take precedence over them.
+        let mut sass_import_paths = opts.sass_import_paths.unwrap_or_default();
+        if opts.sass_auto_load_paths.unwrap_or(true) {
+            sass_import_paths.extend(
+                SASS_AUTO_LOAD_PATHS
+                    .iter()
+                    .map(|dir| target_parent.join(dir))
+                    .filter(|dir| dir.is_dir()),
+            );
+        }
+
+        let postcss_config = opts
+            .postcss_config
+            .unwrap_or_else(|| target_parent.join("postcss.config.js"));
+        let postcss = opts.postcss.unwrap_or_else(|| postcss_config.is_file());
+
+        // Resolve `pkg:` imports of sass from the project's `node_modules`, if there is one. The
+        // node package importer is only used for an auto-detected dir if the sass version supports
+        // it, as older versions reject `--pkg-importer`.
+        let sass_node_pkg_importer = opts.sass_pkg_importer.is_some()
+            || crate::tools::version_at_least(
+                Application::Sass,
+                tools.sass.as_deref(),
+                SASS_PKG_IMPORTER_MIN_VERSION,
+            );
+        let sass_pkg_importer = opts.sass_pkg_importer.or_else(|| {
+            let node_modules = target_parent.join("node_modules");
+            node_modules.is_dir().then_some(node_modules)
+        });
+
+        let wasm_opt_parallelism = opts.wasm_opt_parallelism.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        });
+        ensure!(
+            wasm_opt_parallelism > 0,
+            "the wasm-opt parallelism must be at least 1"
+        );
+
         let cargo_features = if opts.all_features {
             Features::All
         } else {
@@ -120,11 +289,44 @@ impl RtcBuild {
             target,
             target_parent,
             release: opts.release,
+            no_opt: opts.no_opt,
+            cargo_profile: opts.cargo_profile,
+            wasm_opt_profiles: opts.wasm_opt_profiles.unwrap_or_default(),
             public_url: opts.public_url.unwrap_or_else(|| "/".into()),
             filehash: opts.filehash.unwrap_or(true),
+            cargo_target: super::DEFAULT_CARGO_TARGET.into(),
+            is_target_build: false,
+            targets: opts.targets.unwrap_or_default(),
             staging_dist,
             final_dist,
             cargo_features,
+            sass_quiet_deps: opts.sass_quiet_deps,
+            sass_verbose: opts.sass_verbose,
+            sass_no_unicode: opts.sass_no_unicode,
+            sass_error_css: opts.sass_error_css.unwrap_or(false),
+            sass_error_overlay: opts.sass_error_overlay.unwrap_or(false),
+            wasm_opt_pool: WasmOptPool::new(wasm_opt_parallelism),
+            wasm_opt_always_use_output: opts.wasm_opt_always_use_output,
+            wasm_opt_debug_output: opts.wasm_opt_debug_output,
+            wasm_opt_diagnostics: opts.wasm_opt_diagnostics.unwrap_or_default(),
+            wasm_bindgen_debug: opts.wasm_bindgen_debug.unwrap_or(!opts.release),
+            wasm_bindgen_generate_package_json: opts.wasm_bindgen_generate_package_json,
+            wasm_bindgen_out_name: opts.wasm_bindgen_out_name,
+            verify_determinism: opts.verify_determinism,
+            wasm_size_profile: opts.wasm_size_profile,
+            skip_wasm_validation: opts.skip_wasm_validation,
+            experimental_features: opts.experimental_features,
+            sass_charset: opts.sass_charset.unwrap_or_default(),
+            sass_input_syntax: opts.sass_input_syntax.unwrap_or_default(),
+            sass_incremental: SassIncrementalMode::Always,
+            sass_daemons: SassDaemons::new(),
+            sass_silence_deprecations,
+            sass_import_paths,
+            sass_mirror_structure: opts.sass_mirror_structure.unwrap_or(true),
+            postcss,
+            postcss_config,
+            sass_pkg_importer,
+            sass_node_pkg_importer,
             tools,
             hooks,
             inject_autoloader,
@@ -133,6 +335,49 @@ impl RtcBuild {
             pattern_params: opts.pattern_params,
         })
     }
+
+    /// The name of the cargo profile to build with.
+    pub fn cargo_profile(&self) -> &str {
+        match &self.cargo_profile {
+            Some(profile) => profile,
+            None if self.release => "release",
+            None => "dev",
+        }
+    }
+
+    /// The file collecting the wasm-opt debug output of the current build. It is kept in Trunk's
+    /// cache dir, named after the dist dir, so it is never deployed along with the dist dir.
+    pub async fn wasm_opt_debug_file(&self) -> Result<PathBuf> {
+        let dir = crate::tools::cache_dir()
+            .await?
+            .join(super::WASM_OPT_DEBUG_DIR);
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .context("error creating wasm-opt debug output dir")?;
+        let dist_hash = seahash::hash(self.final_dist.to_string_lossy().as_bytes());
+        Ok(dir.join(format!("wasm-opt-{:016x}.txt", dist_hash)))
+    }
+
+    /// Derive the config of a build for the given WASM target triple, placing its output in a
+    /// subdirectory of the dist dir named after the target.
+    pub fn for_target(&self, target: &str) -> Result<Self> {
+        let final_dist = self.final_dist.join(target);
+        if !final_dist.exists() {
+            std::fs::create_dir(&final_dist).with_context(|| {
+                format!("error creating target dist directory {:?}", &final_dist)
+            })?;
+        }
+        let staging_dist = final_dist.join(super::STAGE_DIR);
+
+        Ok(Self {
+            cargo_target: target.into(),
+            is_target_build: true,
+            targets: vec![],
+            final_dist,
+            staging_dist,
+            ..self.clone()
+        })
+    }
 }
 
 /// Runtime config for the watch system.
@@ -154,7 +399,10 @@ impl RtcWatch {
         hooks: Vec<ConfigOptsHook>,
         inject_autoloader: bool,
     ) -> Result<Self> {
-        let build = Arc::new(RtcBuild::new(build_opts, tools, hooks, inject_autoloader)?);
+        let mut build = RtcBuild::new(build_opts, tools, hooks, inject_autoloader)?;
+        // Watch builds recompile the same stylesheets over and over, keep sass running for them.
+        build.sass_incremental = SassIncrementalMode::Watch;
+        let build = Arc::new(build);
 
         // Take the canonical path of each of the specified watch targets.
         let mut paths = vec![];
@@ -229,6 +477,10 @@ impl RtcServe {
         hooks: Vec<ConfigOptsHook>,
         proxies: Option<Vec<ConfigOptsProxy>>,
     ) -> Result<Self> {
+        // Show sass errors right in the browser during development.
+        let mut build_opts = build_opts;
+        build_opts.sass_error_css = build_opts.sass_error_css.or(Some(!build_opts.release));
+        build_opts.sass_error_overlay = build_opts.sass_error_overlay.or(Some(!build_opts.release));
         let watch = Arc::new(RtcWatch::new(
             build_opts,
             watch_opts,
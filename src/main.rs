@@ -9,6 +9,7 @@ mod pipelines;
 mod proxy;
 mod serve;
 mod tools;
+mod trace;
 mod watch;
 
 use std::path::PathBuf;
@@ -17,36 +18,67 @@ use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use tracing_subscriber::prelude::*;
 
+use crate::config::ConfigOpts;
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let cli = Trunk::parse();
+    let mut cli = Trunk::parse();
 
     #[cfg(windows)]
     if let Err(err) = ansi_term::enable_ansi_support() {
         eprintln!("error enabling ANSI support: {:?}", err);
     }
 
-    tracing_subscriber::registry()
-        // Filter spans based on the RUST_LOG env var.
-        .with(tracing_subscriber::EnvFilter::new(if cli.v {
-            "error,trunk=debug"
-        } else {
-            "error,trunk=info"
-        }))
-        // Send a copy of all spans to stdout as JSON.
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_target(false)
-                .with_level(true)
-                .compact(),
-        )
+    // Filter spans based on the verbosity.
+    let filter = tracing_subscriber::filter::Targets::new()
+        .with_default(tracing::Level::ERROR)
+        .with_target(
+            "trunk",
+            if cli.v {
+                tracing::Level::DEBUG
+            } else {
+                tracing::Level::INFO
+            },
+        );
+    let recorder = match &mut cli.action {
+        TrunkSubcommands::Build(build) if build.timings => {
+            let recorder = trace::TraceRecorder::default();
+            build.trace = Some(recorder.clone());
+            Some(recorder)
+        }
+        _ => None,
+    };
+    match recorder {
         // Install this registry as the global tracing registry.
-        .try_init()
-        .context("error initializing logging")?;
+        None => tracing_subscriber::registry()
+            .with(filter)
+            .with(fmt_layer())
+            .try_init(),
+        // The recorder needs the trace level spans of the build, while the log output is filtered
+        // as usual.
+        Some(recorder) => tracing_subscriber::registry()
+            .with(fmt_layer().with_filter(filter))
+            .with(recorder.with_filter(tracing_subscriber::filter::filter_fn(
+                trace::TraceRecorder::is_recorded,
+            )))
+            .try_init(),
+    }
+    .context("error initializing logging")?;
 
     cli.run().await
 }
 
+/// The layer writing the log output to stdout.
+fn fmt_layer<S>() -> impl tracing_subscriber::Layer<S>
+where
+    S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+{
+    tracing_subscriber::fmt::layer()
+        .with_target(false)
+        .with_level(true)
+        .compact()
+}
+
 /// Build, bundle & ship your Rust WASM application to the web.
 #[derive(Parser)]
 #[clap(about, author, version, name = "trunk")]
@@ -59,17 +91,30 @@ struct Trunk {
     /// Enable verbose logging.
     #[clap(short)]
     pub v: bool,
+    /// Only use system installed and previously downloaded tools, failing if one is missing
+    /// instead of downloading it.
+    #[clap(long, global = true)]
+    pub offline: bool,
 }
 
 impl Trunk {
     #[tracing::instrument(level = "trace", skip(self))]
     pub async fn run(self) -> Result<()> {
+        // Tools are downloaded by most commands, from the URLs and through the proxy configured for
+        // all of them.
+        let tools = ConfigOpts::tools(self.config.clone())?;
+        tools::init_http_client(tools.proxy.as_ref())?;
+        tools::init_download_urls(tools.download_urls.as_ref());
+        tools::init_offline(self.offline || tools.offline);
         match self.action {
             TrunkSubcommands::Build(inner) => inner.run(self.config).await,
             TrunkSubcommands::Clean(inner) => inner.run(self.config).await,
             TrunkSubcommands::Serve(inner) => inner.run(self.config).await,
             TrunkSubcommands::Watch(inner) => inner.run(self.config).await,
             TrunkSubcommands::Config(inner) => inner.run(self.config).await,
+            TrunkSubcommands::Tools(inner) => inner.run(self.config).await,
+            TrunkSubcommands::Optimize(inner) => inner.run(self.config).await,
+            TrunkSubcommands::Sass(inner) => inner.run(self.config).await,
         }
     }
 }
@@ -86,4 +131,10 @@ enum TrunkSubcommands {
     Clean(cmd::clean::Clean),
     /// Trunk config controls.
     Config(cmd::config::Config),
+    /// Manage the tools downloaded by Trunk.
+    Tools(cmd::tools::Tools),
+    /// Optimize a WASM file with the wasm-opt version managed by Trunk.
+    Optimize(cmd::optimize::Optimize),
+    /// Use the sass version managed by Trunk.
+    Sass(cmd::sass::Sass),
 }
@@ -0,0 +1,89 @@
+//! Content hashes in the names of additional files generated by wasm-bindgen.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use tokio::fs;
+
+/// The number of hex digits of the content hash added to the file names.
+const HASH_DIGITS: usize = 8;
+
+/// Renames files after a hash of their contents, like `{stem}-{hash}.wasm`, and patches the
+/// references to them in the JS glue code of wasm-bindgen.
+#[derive(Debug, Default)]
+pub(super) struct ContentHashRenamer {
+    /// The renamed files, as pairs of the old and the new file name.
+    renames: Vec<(String, String)>,
+}
+
+impl ContentHashRenamer {
+    /// Rename the given file in its dir after the hash of its contents, returning the new name.
+    pub(super) async fn rename(&mut self, path: &Path) -> Result<String> {
+        let bytes = fs::read(path)
+            .await
+            .with_context(|| format!("error reading {}", path.display()))?;
+        let file_name = path
+            .file_name()
+            .context("file to rename has no file name")?
+            .to_string_lossy()
+            .into_owned();
+        let hashed_name = hashed_file_name(&file_name, seahash::hash(&bytes));
+        fs::rename(path, path.with_file_name(&hashed_name))
+            .await
+            .with_context(|| format!("error renaming {} to {}", file_name, hashed_name))?;
+        self.renames.push((file_name, hashed_name.clone()));
+        Ok(hashed_name)
+    }
+
+    /// Replace the references to the renamed files in the given JS code, which are quoted paths
+    /// relative to the JS file, like `'./module.wasm'` or `new URL('module.wasm', ...)`.
+    pub(super) fn patch_references(&self, js: &str) -> String {
+        let mut js = js.to_owned();
+        for (old, new) in &self.renames {
+            for quote in ['\'', '"', '`'] {
+                for prefix in ["./", ""] {
+                    js = js.replace(
+                        &format!("{quote}{prefix}{old}{quote}"),
+                        &format!("{quote}{prefix}{new}{quote}"),
+                    );
+                }
+            }
+        }
+        js
+    }
+}
+
+/// The name of the file with the given name and content hash, like `{stem}-{hash}.wasm`.
+fn hashed_file_name(file_name: &str, hash: u64) -> String {
+    let hash = &format!("{:016x}", hash)[..HASH_DIGITS];
+    match file_name.rsplit_once('.') {
+        Some((stem, ext)) => format!("{}-{}.{}", stem, hash, ext),
+        None => format!("{}-{}", file_name, hash),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_renamed_references() {
+        assert_eq!(
+            hashed_file_name("module.wasm", 0x1234),
+            "module-00000000.wasm"
+        );
+        assert_eq!(
+            hashed_file_name("module.wasm", 0xdead_beef_0000_0000),
+            "module-deadbeef.wasm"
+        );
+
+        let renamer = ContentHashRenamer {
+            renames: vec![("module.wasm".into(), "module-deadbeef.wasm".into())],
+        };
+        let js = r#"import m from './module.wasm'; new URL("module.wasm", import.meta.url); 'other_module.wasm';"#;
+        assert_eq!(
+            renamer.patch_references(js),
+            r#"import m from './module-deadbeef.wasm'; new URL("module-deadbeef.wasm", import.meta.url); 'other_module.wasm';"#
+        );
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Instant;
 
 use anyhow::{ensure, Context, Result};
 use futures_util::stream::{FuturesUnordered, StreamExt};
@@ -15,13 +16,15 @@ use crate::config::RtcBuild;
 use crate::hooks::{spawn_hooks, wait_hooks};
 use crate::pipelines::rust::RustApp;
 use crate::pipelines::{
-    Attrs, PipelineStage, TrunkAsset, TrunkAssetPipelineOutput, TrunkAssetReference, TRUNK_ID,
+    Attrs, PipelineStage, StageTiming, TrunkAsset, TrunkAssetPipelineOutput, TrunkAssetReference,
+    TRUNK_ID,
 };
 
 const PUBLIC_URL_MARKER_ATTR: &str = "data-trunk-public-url";
 const RELOAD_SCRIPT: &str = include_str!("../autoreload.js");
 
-type AssetPipelineHandles = FuturesUnordered<JoinHandle<Result<TrunkAssetPipelineOutput>>>;
+type AssetPipelineHandles =
+    FuturesUnordered<JoinHandle<Result<(TrunkAssetPipelineOutput, StageTiming)>>>;
 
 /// An HTML assets build pipeline.
 ///
@@ -60,9 +63,9 @@ impl HtmlPipeline {
         })
     }
 
-    /// Spawn a new pipeline.
+    /// Spawn a new pipeline, returning the time each stage of the build took.
     #[tracing::instrument(level = "trace", skip(self))]
-    pub fn spawn(self: Arc<Self>) -> JoinHandle<Result<()>> {
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<Result<Vec<StageTiming>>> {
         // NOTE WELL: this is a pattern to spawn a blocking thread, and then execute a !Send
         // future on the current thread. This is needed because nipper's internals are !Send.
         tokio::task::spawn_blocking(move || Handle::current().block_on(self.run()))
@@ -70,11 +73,18 @@ impl HtmlPipeline {
 
     /// Run this pipeline.
     #[tracing::instrument(level = "trace", skip(self))]
-    async fn run(self: Arc<Self>) -> Result<()> {
+    async fn run(self: Arc<Self>) -> Result<Vec<StageTiming>> {
         tracing::info!("spawning asset pipelines");
+        let mut timings = vec![];
 
         // Spawn and wait on pre-build hooks.
+        let started = Instant::now();
         wait_hooks(spawn_hooks(self.cfg.clone(), PipelineStage::PreBuild)).await?;
+        timings.push(StageTiming {
+            name: "pre_build hooks".into(),
+            duration: started.elapsed(),
+            skipped: vec![],
+        });
 
         // Open the source HTML file for processing.
         let raw_html = fs::read_to_string(&self.target_html_path).await?;
@@ -129,7 +139,7 @@ impl HtmlPipeline {
                     id,
                 )
                 .await?;
-                assets.push(asset);
+                assets.push((format!("{} #{}", asset.name(), id), asset));
             }
         }
 
@@ -148,21 +158,46 @@ impl HtmlPipeline {
                 self.ignore_chan.clone(),
             )
             .await?;
-            assets.push(TrunkAsset::RustApp(app));
+            assets.push(("rust".into(), TrunkAsset::RustApp(app)));
         }
 
         // Spawn all asset pipelines.
         let mut pipelines: AssetPipelineHandles = FuturesUnordered::new();
-        pipelines.extend(assets.into_iter().map(|asset| asset.spawn()));
+        pipelines.extend(assets.into_iter().map(|(name, asset)| {
+            let started = Instant::now();
+            let handle = asset.spawn();
+            tokio::spawn(async move {
+                let output = handle
+                    .await
+                    .context("failed to await asset finalization")?
+                    .context("error from asset pipeline")?;
+                let duration = started.elapsed();
+                let skipped = output.skipped().to_vec();
+                Ok((
+                    output,
+                    StageTiming {
+                        name,
+                        duration,
+                        skipped,
+                    },
+                ))
+            })
+        }));
         // Spawn all build hooks.
+        let started = Instant::now();
         let build_hooks = spawn_hooks(self.cfg.clone(), PipelineStage::Build);
 
         // Finalize asset pipelines.
-        self.finalize_asset_pipelines(&mut target_html, pipelines)
+        self.finalize_asset_pipelines(&mut target_html, pipelines, &mut timings)
             .await?;
 
         // Wait for all build hooks to finish.
         wait_hooks(build_hooks).await?;
+        timings.push(StageTiming {
+            name: "build hooks".into(),
+            duration: started.elapsed(),
+            skipped: vec![],
+        });
 
         // Finalize HTML.
         self.finalize_html(&mut target_html);
@@ -174,9 +209,15 @@ impl HtmlPipeline {
             .context("error writing finalized HTML output")?;
 
         // Spawn and wait on post-build hooks.
+        let started = Instant::now();
         wait_hooks(spawn_hooks(self.cfg.clone(), PipelineStage::PostBuild)).await?;
+        timings.push(StageTiming {
+            name: "post_build hooks".into(),
+            duration: started.elapsed(),
+            skipped: vec![],
+        });
 
-        Ok(())
+        Ok(timings)
     }
 
     /// Finalize asset pipelines & prep the DOM for final output.
@@ -184,12 +225,12 @@ impl HtmlPipeline {
         &self,
         target_html: &mut Document,
         mut pipelines: AssetPipelineHandles,
+        timings: &mut Vec<StageTiming>,
     ) -> Result<()> {
         while let Some(asset_res) = pipelines.next().await {
-            let asset = asset_res
-                .context("failed to await asset finalization")?
-                .context("error from asset pipeline")?;
+            let (asset, timing) = asset_res.context("failed to await asset finalization")??;
             asset.finalize(target_html).await?;
+            timings.push(timing);
         }
         Ok(())
     }
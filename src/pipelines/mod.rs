@@ -1,3 +1,4 @@
+mod content_hash;
 mod copy_dir;
 mod copy_file;
 mod css;
@@ -5,17 +6,29 @@ mod html;
 mod icon;
 mod inline;
 mod js;
+mod postcss;
 mod rust;
 mod sass;
+mod sass_cache;
+mod sass_daemon;
+mod sass_overlay;
+mod target_features;
+mod twiggy;
+mod wasm_bindgen_postprocess;
+mod wasm_opt;
 
 use std::collections::HashMap;
 use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{bail, ensure, Context, Result};
 pub use html::HtmlPipeline;
 use nipper::Document;
+pub use rust::{WasmOptConfig, WasmOptPool};
+pub use sass::{SassIncrementalMode, SASS_DEPRECATIONS};
+pub use sass_daemon::SassDaemons;
 use serde::Deserialize;
 use tokio::fs;
 use tokio::sync::mpsc;
@@ -29,8 +42,9 @@ use crate::pipelines::css::{Css, CssOutput};
 use crate::pipelines::icon::{Icon, IconOutput};
 use crate::pipelines::inline::{Inline, InlineOutput};
 use crate::pipelines::js::{Js, JsOutput};
-use crate::pipelines::rust::{RustApp, RustAppOutput};
+use crate::pipelines::rust::{RustApp, RustAppOutput, RustComponentOutput};
 use crate::pipelines::sass::{Sass, SassOutput};
+use crate::pipelines::wasm_opt::{WasmOptOnlyOutput, WasmOptOnlyPipeline};
 
 const ATTR_INLINE: &str = "data-inline";
 const ATTR_HREF: &str = "href";
@@ -65,6 +79,7 @@ pub enum TrunkAsset {
     CopyFile(CopyFile),
     CopyDir(CopyDir),
     RustApp(RustApp),
+    WasmOpt(WasmOptOnlyPipeline),
 }
 
 impl TrunkAsset {
@@ -95,6 +110,9 @@ impl TrunkAsset {
                     CopyDir::TYPE_COPY_DIR => {
                         Self::CopyDir(CopyDir::new(cfg, html_dir, attrs, id).await?)
                     }
+                    WasmOptOnlyPipeline::TYPE_WASM_OPT => {
+                        Self::WasmOpt(WasmOptOnlyPipeline::new(cfg, html_dir, attrs, id).await?)
+                    }
                     RustApp::TYPE_RUST_APP => {
                         Self::RustApp(RustApp::new(cfg, html_dir, ignore_chan, attrs, id).await?)
                     }
@@ -110,6 +128,21 @@ impl TrunkAsset {
         }
     }
 
+    /// The name of the pipeline of this asset, as shown in build summaries.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Css(_) => "css",
+            Self::Sass(_) => "sass",
+            Self::Js(_) => "js",
+            Self::Icon(_) => "icon",
+            Self::Inline(_) => "inline",
+            Self::CopyFile(_) => "copy-file",
+            Self::CopyDir(_) => "copy-dir",
+            Self::RustApp(_) => "rust",
+            Self::WasmOpt(_) => "wasm-opt",
+        }
+    }
+
     /// Spawn the build pipeline for this asset.
     pub fn spawn(self) -> JoinHandle<Result<TrunkAssetPipelineOutput>> {
         match self {
@@ -121,6 +154,7 @@ impl TrunkAsset {
             Self::CopyFile(inner) => inner.spawn(),
             Self::CopyDir(inner) => inner.spawn(),
             Self::RustApp(inner) => inner.spawn(),
+            Self::WasmOpt(inner) => inner.spawn(),
         }
     }
 }
@@ -135,6 +169,8 @@ pub enum TrunkAssetPipelineOutput {
     CopyFile(CopyFileOutput),
     CopyDir(CopyDirOutput),
     RustApp(RustAppOutput),
+    RustComponent(RustComponentOutput),
+    WasmOpt(WasmOptOnlyOutput),
 }
 
 impl TrunkAssetPipelineOutput {
@@ -148,6 +184,17 @@ impl TrunkAssetPipelineOutput {
             TrunkAssetPipelineOutput::CopyFile(out) => out.finalize(dom).await,
             TrunkAssetPipelineOutput::CopyDir(out) => out.finalize(dom).await,
             TrunkAssetPipelineOutput::RustApp(out) => out.finalize(dom).await,
+            TrunkAssetPipelineOutput::RustComponent(out) => out.finalize(dom).await,
+            TrunkAssetPipelineOutput::WasmOpt(out) => out.finalize(dom).await,
+        }
+    }
+
+    /// The steps of the pipeline skipped as their input was unchanged since the previous build.
+    pub fn skipped(&self) -> &[&'static str] {
+        match self {
+            TrunkAssetPipelineOutput::Sass(out) => &out.skipped,
+            TrunkAssetPipelineOutput::RustApp(out) => &out.skipped,
+            _ => &[],
         }
     }
 }
@@ -262,6 +309,17 @@ pub enum PipelineStage {
     PostBuild,
 }
 
+/// The time it took to run one stage of a build.
+#[derive(Clone, Debug)]
+pub struct StageTiming {
+    /// The name of the stage, like `pre_build hooks` or `rust #2`.
+    pub name: String,
+    /// The time from spawning the stage until it finished.
+    pub duration: Duration,
+    /// The steps of the stage skipped as their input was unchanged since the previous build.
+    pub skipped: Vec<&'static str>,
+}
+
 /// Create the CSS selector for selecting a trunk link by ID.
 pub(self) fn trunk_id_selector(id: usize) -> String {
     format!(r#"link[{}="{}"]"#, TRUNK_ID, id)
@@ -0,0 +1,86 @@
+//! Post-processing of compiled stylesheets with PostCSS.
+//!
+//! PostCSS is distributed through npm only, so instead of being downloaded like the other tools,
+//! it is taken from the `node_modules` of the project, from the `PATH`, or run with `npx`.
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+use anyhow::{ensure, Context, Result};
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::config::RtcBuild;
+
+/// The command running PostCSS, along with the arguments preceding the ones for PostCSS.
+fn postcss_command(project_dir: &Path) -> Result<(PathBuf, Vec<&'static str>)> {
+    let bin = if cfg!(target_os = "windows") {
+        "postcss.cmd"
+    } else {
+        "postcss"
+    };
+    let local = project_dir.join("node_modules").join(".bin").join(bin);
+    if local.is_file() {
+        return Ok((local, vec![]));
+    }
+    if let Ok(path) = which::which("postcss") {
+        return Ok((path, vec![]));
+    }
+    let npx = which::which("npx").context(
+        "postcss is enabled, but neither `postcss` nor `npx` was found; install `postcss-cli` \
+         with `npm install --save-dev postcss postcss-cli autoprefixer`",
+    )?;
+    // Without `--no-install`, npx would prompt for installing the package.
+    Ok((npx, vec!["--no-install", "postcss"]))
+}
+
+/// Run the given CSS through PostCSS, using the configured PostCSS config file if it exists, or
+/// else the autoprefixer plugin.
+///
+/// `from` is the path of the stylesheet the CSS was compiled from, which PostCSS uses for
+/// resolving relative URLs and for finding the applicable browserslist config.
+pub(super) async fn run_postcss(cfg: &RtcBuild, css: String, from: &Path) -> Result<String> {
+    let (postcss, mut args) = postcss_command(&cfg.target_parent)?;
+    // PostCSS looks for the config in the given dir, not a file.
+    let config_dir = cfg
+        .postcss_config
+        .is_file()
+        .then(|| cfg.postcss_config.parent())
+        .flatten()
+        .map(|dir| dir.display().to_string());
+    match &config_dir {
+        Some(dir) => args.extend(["--config", dir]),
+        None => args.extend(["--use", "autoprefixer"]),
+    }
+    let from = from.display().to_string();
+    args.extend(["--from", &from]);
+
+    tracing::debug!(?args, "postcss args");
+    let mut child = Command::new(&postcss)
+        .args(&args)
+        .current_dir(&cfg.target_parent)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("error spawning postcss")?;
+    let mut stdin = child.stdin.take().context("postcss has no stdin")?;
+    // Write the input concurrently, as postcss may start writing output before reading it all.
+    let write = tokio::spawn(async move {
+        stdin.write_all(css.as_bytes()).await?;
+        stdin.shutdown().await
+    });
+    let output = child
+        .wait_with_output()
+        .await
+        .context("error waiting for postcss")?;
+    write
+        .await
+        .context("error writing to postcss")?
+        .context("error writing to postcss")?;
+    ensure!(
+        output.status.success(),
+        "postcss call returned a bad status: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    String::from_utf8(output.stdout).context("postcss output is not valid UTF-8")
+}
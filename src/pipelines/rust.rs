@@ -1,6 +1,6 @@
 //! Rust application pipeline.
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::iter::Iterator;
 use std::path::{Path, PathBuf};
 use std::process::Stdio;
@@ -10,12 +10,19 @@ use std::sync::Arc;
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use cargo_lock::Lockfile;
 use nipper::Document;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, Semaphore, SemaphorePermit};
 use tokio::task::JoinHandle;
 
+use super::content_hash::ContentHashRenamer;
+use super::target_features::{validate_wasm, Producers, TargetFeatures};
+use super::twiggy::log_size_profile;
+use super::wasm_bindgen_postprocess::remove_unused_exports;
 use super::{Attrs, TrunkAssetPipelineOutput, ATTR_HREF, SNIPPETS_DIR};
 use crate::common::{self, copy_dir_recursive, path_exists};
 use crate::config::{CargoMetadata, ConfigOptsTools, Features, RtcBuild};
@@ -47,16 +54,47 @@ pub struct RustApp {
     no_demangle: bool,
     /// An option to instruct wasm-bindgen to enable reference types.
     reference_types: bool,
+    /// Enable reference types if the WASM binary uses them, as `data-reference-types` is not set.
+    detect_reference_types: bool,
     /// An option to instruct wasm-bindgen to enable weak references.
     weak_refs: bool,
-    /// An optional optimization setting that enables wasm-opt. Can be nothing, `0` (default), `1`,
-    /// `2`, `3`, `4`, `s or `z`. Using `0` disables wasm-opt completely.
-    wasm_opt: WasmOptLevel,
+    /// The target wasm-bindgen generates the bindings for.
+    bindgen_target: BindgenTarget,
+    /// An option to instruct wasm-bindgen to not generate the JS import shims, for runtimes
+    /// which provide their own imports.
+    omit_imports: bool,
+    /// Settings for running wasm-opt on the wasm-bindgen output.
+    wasm_opt: WasmOptConfig,
     /// Name for the module. Is binary name if given, otherwise it is the name of the cargo
     /// project.
     name: String,
     /// Whether to create a loader shim script
     loader_shim: bool,
+    /// Whether this is a WASM component model project, built with `cargo component`.
+    component: bool,
+    /// An optional flag to keep the exports added by the linker (experimental).
+    keep_lld_exports: bool,
+    /// An optional flag to generate bindings for browsers only.
+    browser: bool,
+    /// An optional setting for when `TextEncoder#encodeInto` is used: `always`, `never` or `test`.
+    encode_into: Option<String>,
+    /// An optional flag to pre-initialize the WASM module with wizer.
+    wizen: bool,
+    /// An optional name of the global defined by the `no-modules` bindings, instead of
+    /// `wasm_bindgen`.
+    no_modules_global: Option<String>,
+    /// An optional flag to leave out the default URL of the WASM file from the `init` function
+    /// of the bindings, which then has to be passed the URL.
+    omit_default_module_path: bool,
+    /// An optional flag to place the wasm-bindgen output in a subdir of the dist dir named after
+    /// the wasm-bindgen target, like `web/`.
+    output_subdir: bool,
+    /// An optional flag to remove the functions and classes of the JS glue code which are
+    /// never referenced, including exports other than the default one.
+    remove_unused_exports: bool,
+    /// An optional flag to have wasm-bindgen split linked modules into files of their own, which
+    /// are named after a hash of their contents.
+    split_linked_modules: bool,
 }
 
 /// Describes how the rust application is used.
@@ -83,6 +121,151 @@ impl FromStr for RustAppType {
     }
 }
 
+/// The target wasm-bindgen generates the bindings for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindgenTarget {
+    /// ES module for direct use in the browser, the default for main applications.
+    Web,
+    /// A script defining a global `wasm_bindgen`, the default for web workers.
+    ///
+    /// Deprecated by wasm-bindgen in favor of `Web`, as all current browsers support ES modules.
+    NoModules,
+    /// ES module for use with a bundler like webpack.
+    Bundler,
+    /// CommonJS module for use with Node.js.
+    NodeJs,
+    /// ES module for use with Deno.
+    Deno,
+    /// A WASI preview 2 component for server-side runtimes like wasmtime, built for the
+    /// `wasm32-wasip2` target.
+    ///
+    /// Not a target of wasm-bindgen, which is skipped just like for component model projects.
+    WasiP2,
+}
+
+impl BindgenTarget {
+    /// The default target for the given type of application.
+    fn default_for(app_type: RustAppType) -> Self {
+        match app_type {
+            RustAppType::Main => Self::Web,
+            RustAppType::Worker => Self::NoModules,
+        }
+    }
+}
+
+impl FromStr for BindgenTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "web" => Self::Web,
+            "no-modules" => Self::NoModules,
+            "bundler" => Self::Bundler,
+            "nodejs" => Self::NodeJs,
+            "deno" => Self::Deno,
+            "wasi-p2" => Self::WasiP2,
+            _ => bail!(
+                r#"unknown `data-bindgen-target="{}"` value for <link data-trunk rel="rust" .../> attr; please ensure the value is lowercase and is a supported wasm-bindgen target"#,
+                s
+            ),
+        })
+    }
+}
+
+impl AsRef<str> for BindgenTarget {
+    fn as_ref(&self) -> &str {
+        match self {
+            Self::Web => "web",
+            Self::NoModules => "no-modules",
+            Self::Bundler => "bundler",
+            Self::NodeJs => "nodejs",
+            Self::Deno => "deno",
+            Self::WasiP2 => "wasi-p2",
+        }
+    }
+}
+
+/// The cargo target of WASI preview 2 components.
+const WASI_P2_CARGO_TARGET: &str = "wasm32-wasip2";
+
+/// The bindgen target of a build, given the target of its `data-bindgen-target` attr.
+///
+/// The cargo target of the build decides whether a WASI component is built: builds for
+/// `wasm32-wasip2` always are, while the other targets of a multi-target build never are. Only a
+/// single-target build is switched to `wasm32-wasip2` by the `wasi-p2` attr.
+fn resolve_bindgen_target(
+    cfg: &RtcBuild,
+    attr: Option<BindgenTarget>,
+    app_type: RustAppType,
+) -> BindgenTarget {
+    if cfg.cargo_target == WASI_P2_CARGO_TARGET {
+        return BindgenTarget::WasiP2;
+    }
+    match attr {
+        Some(BindgenTarget::WasiP2) if cfg.is_target_build => BindgenTarget::default_for(app_type),
+        Some(target) => target,
+        None => BindgenTarget::default_for(app_type),
+    }
+}
+
+/// The profiles built into cargo, which have a default wasm-opt config.
+const BUILTIN_CARGO_PROFILES: &[&str] = &["release", "bench", "dev", "debug", "test"];
+
+/// Whether the given cargo profile inherits from the `release` profile, directly or through other
+/// custom profiles, as set by `CARGO_PROFILE_<NAME>_INHERITS` or in the `[profile]` table of the
+/// given manifest. Manifests which can not be read are treated as not declaring any profiles.
+pub(super) fn profile_inherits_release(profile: &str, manifest: &Path) -> bool {
+    let profiles = std::fs::read_to_string(manifest)
+        .ok()
+        .and_then(|manifest| manifest.parse::<toml::Value>().ok())
+        .and_then(|manifest| manifest.get("profile").cloned());
+    let mut profile = profile.to_owned();
+    // Cargo rejects cyclic inheritance, but a malformed manifest must not hang the build.
+    let mut visited = vec![];
+    while !visited.contains(&profile) {
+        visited.push(profile.clone());
+        let var = format!(
+            "CARGO_PROFILE_{}_INHERITS",
+            profile.to_uppercase().replace('-', "_")
+        );
+        let inherits = std::env::var(var).ok().or_else(|| {
+            profiles
+                .as_ref()?
+                .get(&profile)?
+                .get("inherits")?
+                .as_str()
+                .map(str::to_owned)
+        });
+        match inherits {
+            Some(inherits) if inherits == "release" => return true,
+            Some(inherits) if !BUILTIN_CARGO_PROFILES.contains(&inherits.as_str()) => {
+                profile = inherits
+            }
+            _ => return false,
+        }
+    }
+    false
+}
+
+/// The attributes configuring wasm-bindgen or the loading of its output in the browser, which
+/// have no effect on WASI preview 2 components.
+const WASI_P2_INCOMPATIBLE_ATTRS: &[&str] = &[
+    "data-bindgen-browser",
+    "data-bindgen-encode-into",
+    "data-bindgen-no-modules-global",
+    "data-bindgen-omit-default-module-path",
+    "data-bindgen-output-subdir",
+    "data-bindgen-remove-unused-exports",
+    "data-bindgen-split-linked-modules",
+    "data-keep-lld-exports",
+    "data-loader-shim",
+    "data-omit-imports",
+    "data-reference-types",
+    "data-typescript",
+    "data-weak-refs",
+    "data-wizen",
+];
+
 impl RustApp {
     pub const TYPE_RUST_APP: &'static str = "rust";
 
@@ -109,7 +292,7 @@ impl RustApp {
             })
             .unwrap_or_else(|| html_dir.join("Cargo.toml"));
         let bin = attrs.get("data-bin").map(|val| val.to_string());
-        let keep_debug = attrs.contains_key("data-keep-debug");
+        let keep_debug = attrs.contains_key("data-keep-debug") || keep_debug_by_default(&cfg);
         let typescript = attrs.contains_key("data-typescript");
         let no_demangle = attrs.contains_key("data-no-demangle");
         let app_type = attrs
@@ -117,20 +300,114 @@ impl RustApp {
             .map(|s| s.as_str())
             .unwrap_or("main")
             .parse()?;
-        let reference_types = attrs.contains_key("data-reference-types");
+        let reference_types = attrs.get("data-reference-types").map(|val| val != "false");
         let weak_refs = attrs.contains_key("data-weak-refs");
-        let wasm_opt = attrs
-            .get("data-wasm-opt")
+        let bindgen_target_attr = attrs
+            .get("data-bindgen-target")
             .map(|val| val.parse())
-            .transpose()?
-            .unwrap_or_else(|| {
-                if cfg.release {
-                    Default::default()
-                } else {
-                    WasmOptLevel::Off
-                }
-            });
+            .transpose()?;
+        if bindgen_target_attr == Some(BindgenTarget::WasiP2) {
+            if let Some(attr) = WASI_P2_INCOMPATIBLE_ATTRS
+                .iter()
+                .find(|attr| attrs.contains_key(**attr))
+            {
+                bail!(
+                    "`{}` can not be used with the `wasi-p2` target, as wasm-bindgen is not used \
+                     for WASI components",
+                    attr
+                );
+            }
+        }
+        // The attrs are validated against the configured target, even when building a WASI
+        // component for the `wasm32-wasip2` target of a multi-target build.
+        let bindgen_target =
+            bindgen_target_attr.unwrap_or_else(|| BindgenTarget::default_for(app_type));
+        let built_target = resolve_bindgen_target(&cfg, bindgen_target_attr, app_type);
+        ensure!(
+            built_target != BindgenTarget::WasiP2 || app_type == RustAppType::Main,
+            "the `wasi-p2` target can not be used for web workers"
+        );
+        let omit_imports = attrs.contains_key("data-omit-imports");
+        let keep_lld_exports = attrs.contains_key("data-keep-lld-exports");
+        let browser = attrs.contains_key("data-bindgen-browser");
+        let encode_into = attrs.get("data-bindgen-encode-into").cloned();
+        let wizen = attrs.contains_key("data-wizen");
+        let no_modules_global = attrs.get("data-bindgen-no-modules-global").cloned();
+        let omit_default_module_path = attrs.contains_key("data-bindgen-omit-default-module-path");
+        let output_subdir = attrs.contains_key("data-bindgen-output-subdir");
+        let remove_unused_exports = attrs.contains_key("data-bindgen-remove-unused-exports");
+        // The glue code of other targets is used by code which Trunk doesn't know about.
+        ensure!(
+            !remove_unused_exports || bindgen_target == BindgenTarget::Web,
+            "`data-bindgen-remove-unused-exports` can only be used with the `web` wasm-bindgen \
+             target"
+        );
+        // Only the bindings of these targets fetch the WASM file by themselves.
+        ensure!(
+            !omit_default_module_path
+                || matches!(
+                    bindgen_target,
+                    BindgenTarget::Web | BindgenTarget::NoModules
+                ),
+            "`data-bindgen-omit-default-module-path` can only be used with the `web` and \
+             `no-modules` wasm-bindgen targets"
+        );
+        let split_linked_modules = attrs.contains_key("data-bindgen-split-linked-modules");
+        ensure!(
+            no_modules_global.is_none() || bindgen_target == BindgenTarget::NoModules,
+            "`data-bindgen-no-modules-global` can only be used with the `no-modules` wasm-bindgen \
+             target"
+        );
+        if bindgen_target == BindgenTarget::NoModules && attrs.contains_key("data-bindgen-target") {
+            tracing::warn!(
+                "the `no-modules` wasm-bindgen target is deprecated, consider migrating to the \
+                 `web` target, which is supported by all current browsers"
+            );
+        }
+        if let Some(encode_into) = &encode_into {
+            ensure!(
+                matches!(encode_into.as_str(), "always" | "never" | "test"),
+                "`data-bindgen-encode-into` must be one of `always`, `never` or `test`, got `{}`",
+                encode_into
+            );
+        }
+        ensure!(
+            !browser || bindgen_target == BindgenTarget::Web,
+            "`data-bindgen-browser` can only be used with the `web` wasm-bindgen target"
+        );
+        if keep_lld_exports {
+            ensure!(
+                cfg.experimental_features,
+                "`data-keep-lld-exports` uses an experimental wasm-bindgen flag and requires \
+                 `experimental_features = true` in the `[build]` section of `Trunk.toml`"
+            );
+            tracing::warn!(
+                "keep-lld-exports is an experimental wasm-bindgen flag and its behavior may change \
+                 in future versions"
+            );
+        }
+        ensure!(
+            !(omit_imports && bindgen_target == BindgenTarget::Web),
+            "`data-omit-imports` can not be used with the `web` wasm-bindgen target, as the \
+             resulting bindings would be broken in the browser"
+        );
         let manifest = CargoMetadata::new(&manifest_href).await?;
+        let workspace_manifest = manifest.metadata.workspace_root.join("Cargo.toml");
+        let inherits_release = cfg.release
+            || profile_inherits_release(cfg.cargo_profile(), workspace_manifest.as_std_path());
+        let wasm_opt_profile = WasmOptConfig::for_profile(
+            cfg.cargo_profile(),
+            &cfg.wasm_opt_profiles,
+            inherits_release,
+        );
+        let wasm_opt = WasmOptConfig::from_attrs(&attrs, cfg.release, wasm_opt_profile, true)?;
+        if wasm_opt.preserve_debug_info && !keep_debug {
+            tracing::warn!(
+                "`data-wasm-opt-preserve-debug-info` has no effect without `data-keep-debug`, as \
+                 wasm-bindgen strips the debug info before wasm-opt runs"
+            );
+        }
+        let component = is_component_project(&manifest).await?;
         let id = Some(id);
         let name = bin.clone().unwrap_or_else(|| manifest.package.name.clone());
 
@@ -171,12 +448,25 @@ impl RustApp {
             keep_debug,
             typescript,
             no_demangle,
-            reference_types,
+            reference_types: reference_types.unwrap_or_default(),
+            detect_reference_types: reference_types.is_none(),
             weak_refs,
+            bindgen_target: built_target,
+            omit_imports,
             wasm_opt,
             app_type,
             name,
             loader_shim,
+            component,
+            keep_lld_exports,
+            browser,
+            encode_into,
+            wizen,
+            no_modules_global,
+            omit_default_module_path,
+            output_subdir,
+            remove_unused_exports,
+            split_linked_modules,
         })
     }
 
@@ -187,7 +477,10 @@ impl RustApp {
     ) -> Result<Self> {
         let path = html_dir.join("Cargo.toml");
         let manifest = CargoMetadata::new(&path).await?;
+        let component = is_component_project(&manifest).await?;
         let name = manifest.package.name.clone();
+        let keep_debug = keep_debug_by_default(&cfg);
+        let bindgen_target = resolve_bindgen_target(&cfg, None, RustAppType::Main);
 
         Ok(Self {
             id: None,
@@ -196,15 +489,28 @@ impl RustApp {
             manifest,
             ignore_chan,
             bin: None,
-            keep_debug: false,
+            keep_debug,
             typescript: false,
             no_demangle: false,
             reference_types: false,
+            detect_reference_types: true,
             weak_refs: false,
-            wasm_opt: WasmOptLevel::Off,
+            bindgen_target,
+            omit_imports: false,
+            wasm_opt: WasmOptConfig::off(),
             app_type: RustAppType::Main,
             name,
             loader_shim: false,
+            component,
+            keep_lld_exports: false,
+            browser: false,
+            encode_into: None,
+            wizen: false,
+            no_modules_global: None,
+            omit_default_module_path: false,
+            output_subdir: false,
+            remove_unused_exports: false,
+            split_linked_modules: false,
         })
     }
 
@@ -217,8 +523,27 @@ impl RustApp {
     #[tracing::instrument(level = "trace", skip(self))]
     async fn build(mut self) -> Result<TrunkAssetPipelineOutput> {
         let (wasm, hashed_name) = self.cargo_build().await?;
+        if self.component || self.bindgen_target == BindgenTarget::WasiP2 {
+            let pipeline = ComponentModelBuildPipeline {
+                id: self.id,
+                cfg: self.cfg.clone(),
+            };
+            let output = pipeline.run(&wasm, &hashed_name).await?;
+            return Ok(TrunkAssetPipelineOutput::RustComponent(output));
+        }
+        let features = TargetFeatures::read(&wasm).await?;
+        if self.detect_reference_types {
+            self.reference_types = features.contains("reference-types");
+            if self.reference_types {
+                tracing::debug!("enabling reference types, as the WASM binary uses them");
+            }
+        }
+        self.wasm_opt.detect_features(&features);
         let output = self.wasm_bindgen_build(wasm.as_ref(), &hashed_name).await?;
         self.wasm_opt_build(&output.wasm_output).await?;
+        if self.wizen {
+            self.wizer_build(&output.wasm_output).await?;
+        }
         Ok(TrunkAssetPipelineOutput::RustApp(output))
     }
 
@@ -227,13 +552,27 @@ impl RustApp {
         tracing::info!("building {}", &self.manifest.package.name);
 
         // Spawn the cargo build process.
-        let mut args = vec![
+        // WASI components are built for their own target, regardless of the targets of the build.
+        let cargo_target = match self.bindgen_target {
+            BindgenTarget::WasiP2 => WASI_P2_CARGO_TARGET,
+            _ => &self.cfg.cargo_target,
+        };
+        let arg_target = format!("--target={}", cargo_target);
+        let mut args = vec![];
+        if self.component {
+            args.push("component");
+        }
+        args.extend([
             "build",
-            "--target=wasm32-unknown-unknown",
+            &arg_target,
             "--manifest-path",
             &self.manifest.manifest_path,
-        ];
-        if self.cfg.release {
+        ]);
+        let arg_profile;
+        if let Some(profile) = &self.cfg.cargo_profile {
+            arg_profile = format!("--profile={}", profile);
+            args.push(&arg_profile);
+        } else if self.cfg.release {
             args.push("--release");
         }
         if let Some(bin) = &self.bin {
@@ -324,11 +663,15 @@ impl RustApp {
         let wasm_bytes = fs::read(&wasm)
             .await
             .context("error reading wasm file for hash generation")?;
+        let out_name = match (&self.app_type, &self.cfg.wasm_bindgen_out_name) {
+            (RustAppType::Main, Some(out_name)) => out_name,
+            _ => &self.name,
+        };
         let hashed_name = self
             .cfg
             .filehash
-            .then(|| format!("{}-{:x}", self.name, seahash::hash(&wasm_bytes)))
-            .unwrap_or_else(|| self.name.clone());
+            .then(|| format!("{}-{:x}", out_name, seahash::hash(&wasm_bytes)))
+            .unwrap_or_else(|| out_name.clone());
 
         Ok((wasm.into_std_path_buf(), hashed_name))
     }
@@ -343,31 +686,36 @@ impl RustApp {
         };
 
         let version = find_wasm_bindgen_version(&self.cfg.tools, &self.manifest);
+        if let (Some(cli_version), Some(crate_version)) = (
+            &self.cfg.tools.wasm_bindgen,
+            find_wasm_bindgen_crate_version(&self.manifest),
+        ) {
+            check_wasm_bindgen_version(cli_version, &crate_version);
+        }
         let wasm_bindgen = tools::get(Application::WasmBindgen, version.as_deref()).await?;
 
-        // Ensure our output dir is in place.
         let wasm_bindgen_name = Application::WasmBindgen.name();
         let mode_segment = if self.cfg.release { "release" } else { "debug" };
-        let bindgen_out = self
+        let mut bindgen_out = self
             .manifest
             .metadata
             .target_directory
             .join(wasm_bindgen_name)
-            .join(mode_segment);
-        fs::create_dir_all(bindgen_out.as_path())
-            .await
-            .context("error creating wasm-bindgen output dir")?;
+            .join(&self.cfg.cargo_target)
+            .join(mode_segment)
+            .join(&self.name);
+        // The same app may be built for several targets, each into its own subdir.
+        if self.output_subdir {
+            bindgen_out.push(self.bindgen_target.as_ref());
+        }
 
         // Build up args for calling wasm-bindgen.
         let arg_out_path = format!("--out-dir={}", bindgen_out);
         let arg_out_name = format!("--out-name={}", &hashed_name);
         let target_wasm = wasm.to_string_lossy().to_string();
-        let target_type = match self.app_type {
-            RustAppType::Main => "--target=web",
-            RustAppType::Worker => "--target=no-modules",
-        };
+        let arg_target = format!("--target={}", self.bindgen_target.as_ref());
 
-        let mut args = vec![target_type, &arg_out_path, &arg_out_name, &target_wasm];
+        let mut args: Vec<&str> = vec![&arg_target, &arg_out_path, &arg_out_name, &target_wasm];
         if self.keep_debug {
             args.push("--keep-debug");
         }
@@ -380,47 +728,163 @@ impl RustApp {
         if self.weak_refs {
             args.push("--weak-refs");
         }
+        if self.omit_imports {
+            args.push("--omit-imports");
+        }
+        if self.keep_lld_exports {
+            args.push("--keep-lld-exports");
+        }
+        if self.browser {
+            args.push("--browser");
+        }
+        if self.cfg.wasm_bindgen_debug {
+            args.push("--debug");
+        }
+        let arg_encode_into = self
+            .encode_into
+            .as_ref()
+            .map(|val| format!("--encode-into={}", val));
+        args.extend(arg_encode_into.as_deref());
+        if let Some(global) = &self.no_modules_global {
+            args.extend(["--no-modules-global", global]);
+        }
+        if self.omit_default_module_path {
+            args.push("--omit-default-module-path");
+        }
+        if self.split_linked_modules {
+            args.push("--split-linked-modules");
+        }
 
         if !self.typescript {
             args.push("--no-typescript");
         }
 
-        // Invoke wasm-bindgen.
-        tracing::info!("calling wasm-bindgen for {}", self.name);
-        common::run_command(wasm_bindgen_name, &wasm_bindgen, &args)
-            .await
-            .map_err(|err| check_target_not_found_err(err, wasm_bindgen_name))?;
+        // The output of the previous run is still valid if neither the input nor the arguments
+        // changed, like after changing a stylesheet in watch mode.
+        let fingerprint_path = bindgen_out.join(WASM_BINDGEN_FINGERPRINT_FILE);
+        let fingerprint = wasm_bindgen_fingerprint(&wasm_bindgen, wasm, &args).await?;
+        let previous_fingerprint = fs::read_to_string(&fingerprint_path).await.ok();
+        let bindgen_unchanged = previous_fingerprint.as_deref() == Some(fingerprint.as_str());
+        if bindgen_unchanged {
+            tracing::info!(
+                "skipping wasm-bindgen for {}, its input is unchanged",
+                self.name
+            );
+        } else {
+            // Ensure our output dir is in place. It is dedicated to this application and starts
+            // out empty, so that everything in it after calling wasm-bindgen was generated by
+            // this run.
+            common::remove_dir_all(bindgen_out.clone().into_std_path_buf())
+                .await
+                .context("error cleaning wasm-bindgen output dir")?;
+            fs::create_dir_all(bindgen_out.as_path())
+                .await
+                .context("error creating wasm-bindgen output dir")?;
+
+            // wasm-bindgen fails on malformed input with errors hard to make sense of.
+            if !self.cfg.skip_wasm_validation {
+                validate_wasm(wasm).await.with_context(|| {
+                    format!(
+                        "the WASM binary {} built by cargo is malformed, set \
+                         `skip_wasm_validation` if wasm-bindgen should process it nonetheless",
+                        wasm.display()
+                    )
+                })?;
+            }
+
+            // Invoke wasm-bindgen.
+            tracing::info!("calling wasm-bindgen for {}", self.name);
+            common::run_command(wasm_bindgen_name, &wasm_bindgen, &args)
+                .await
+                .map_err(|err| check_target_not_found_err(err, wasm_bindgen_name))?;
+            fs::write(&fingerprint_path, fingerprint)
+                .await
+                .context("error writing wasm-bindgen fingerprint")?;
+        }
 
-        // Copy the generated WASM & JS loader to the dist dir.
+        // Copy all generated files, like the WASM & JS loader, to the dist dir, or to a subdir named
+        // after the target, so that the outputs of several targets don't conflict.
         tracing::info!("copying generated wasm-bindgen artifacts");
+        let out_subdir = self.output_subdir.then(|| self.bindgen_target.as_ref());
+        let dist_dir = match out_subdir {
+            Some(subdir) => self.cfg.staging_dist.join(subdir),
+            None => self.cfg.staging_dist.clone(),
+        };
+        fs::create_dir_all(&dist_dir)
+            .await
+            .context("error creating wasm-bindgen output subdir")?;
+        // The paths of the output files relative to the dist dir.
+        let dist_name = |name: &str| match out_subdir {
+            Some(subdir) => format!("{}/{}", subdir, name),
+            None => name.to_owned(),
+        };
         let hashed_js_name = format!("{}.js", &hashed_name);
         let hashed_wasm_name = format!("{}_bg.wasm", &hashed_name);
         let hashed_ts_name = format!("{}.d.ts", &hashed_name);
-        let js_loader_path = bindgen_out.join(&hashed_js_name);
-        let js_loader_path_dist = self.cfg.staging_dist.join(&hashed_js_name);
-        let wasm_path = bindgen_out.join(&hashed_wasm_name);
-        let wasm_path_dist = self.cfg.staging_dist.join(&hashed_wasm_name);
+        let global_name = self
+            .no_modules_global
+            .clone()
+            .unwrap_or_else(|| "wasm_bindgen".into());
         let hashed_loader_name = self
             .loader_shim
             .then(|| format!("{}_loader.js", &hashed_name));
-        let loader_shim_path = hashed_loader_name
-            .as_ref()
-            .map(|m| self.cfg.staging_dist.join(m));
+        let loader_shim_path = hashed_loader_name.as_ref().map(|m| dist_dir.join(m));
 
-        fs::copy(js_loader_path, js_loader_path_dist)
-            .await
-            .context("error copying JS loader file to stage dir")?;
-        fs::copy(wasm_path, wasm_path_dist)
-            .await
-            .context("error copying wasm file to stage dir")?;
+        let bindgen_output = WasmBindgenOutput::read(bindgen_out.as_std_path()).await?;
+        for name in [&hashed_js_name, &hashed_wasm_name] {
+            ensure!(
+                bindgen_output.contains(name),
+                "wasm-bindgen did not generate the expected file {}",
+                name
+            );
+        }
+        for path in &bindgen_output.files {
+            let file_name = path
+                .file_name()
+                .context("invalid wasm-bindgen output file")?;
+            fs::copy(path, dist_dir.join(file_name))
+                .await
+                .with_context(|| format!("error copying {:?} to stage dir", file_name))?;
+        }
 
-        if self.typescript {
-            let ts_path = bindgen_out.join(&hashed_ts_name);
-            let ts_path_dist = self.cfg.staging_dist.join(&hashed_ts_name);
+        if self.remove_unused_exports {
+            let js_path = dist_dir.join(&hashed_js_name);
+            let js = fs::read_to_string(&js_path)
+                .await
+                .context("error reading wasm-bindgen JS output")?;
+            let (stripped, removed) = remove_unused_exports(&js);
+            tracing::info!(
+                "removed {} unused functions and classes from {}, shrinking it from {} to {} bytes",
+                removed.len(),
+                hashed_js_name,
+                js.len(),
+                stripped.len()
+            );
+            tracing::debug!(?removed, "removed JS declarations");
+            fs::write(&js_path, stripped)
+                .await
+                .context("error writing wasm-bindgen JS output")?;
+        }
 
-            fs::copy(ts_path, ts_path_dist)
+        // The split modules have generated names, which don't change with their contents.
+        if self.split_linked_modules && self.cfg.filehash {
+            let mut renamer = ContentHashRenamer::default();
+            for path in &bindgen_output.files {
+                let file_name = path.file_name().unwrap_or_default();
+                let is_module = matches!(path.extension(), Some(ext) if ext == "wasm")
+                    && file_name != hashed_wasm_name.as_str();
+                if is_module {
+                    let hashed_name = renamer.rename(&dist_dir.join(file_name)).await?;
+                    tracing::debug!(?file_name, %hashed_name, "renamed split linked module");
+                }
+            }
+            let js_path = dist_dir.join(&hashed_js_name);
+            let js = fs::read_to_string(&js_path)
+                .await
+                .context("error reading wasm-bindgen JS output")?;
+            fs::write(&js_path, renamer.patch_references(&js))
                 .await
-                .context("error copying TS files to stage dir")?;
+                .context("error writing wasm-bindgen JS output")?;
         }
 
         if let Some(ref m) = loader_shim_path {
@@ -431,8 +895,8 @@ impl RustApp {
             loader_f
                 .write_all(
                     format!(
-                        r#"importScripts("./{}");wasm_bindgen("./{}");"#,
-                        hashed_js_name, hashed_wasm_name
+                        r#"importScripts("./{}");{}("./{}");"#,
+                        hashed_js_name, global_name, hashed_wasm_name
                     )
                     .as_bytes(),
                 )
@@ -450,43 +914,106 @@ impl RustApp {
             None
         };
 
+        if self.cfg.wasm_bindgen_generate_package_json && self.app_type == RustAppType::Main {
+            let package_json = package_json(
+                &self.manifest.package,
+                &hashed_js_name,
+                ts_output.as_deref(),
+            );
+            let package_json = serde_json::to_vec_pretty(&package_json)
+                .context("error serializing package.json")?;
+            fs::write(dist_dir.join("package.json"), package_json)
+                .await
+                .context("error writing package.json")?;
+        }
+
         // Check for any snippets, and copy them over.
         let snippets_dir = bindgen_out.join(SNIPPETS_DIR);
         if path_exists(&snippets_dir).await? {
-            copy_dir_recursive(
-                bindgen_out.join(SNIPPETS_DIR),
-                self.cfg.staging_dist.join(SNIPPETS_DIR),
-            )
-            .await
-            .context("error copying snippets dir to stage dir")?;
+            copy_dir_recursive(bindgen_out.join(SNIPPETS_DIR), dist_dir.join(SNIPPETS_DIR))
+                .await
+                .context("error copying snippets dir to stage dir")?;
         }
 
         Ok(RustAppOutput {
             id: self.id,
             cfg: self.cfg.clone(),
-            js_output: hashed_js_name,
-            wasm_output: hashed_wasm_name,
-            ts_output,
-            loader_shim_output: hashed_loader_name,
+            js_output: dist_name(&hashed_js_name),
+            wasm_output: dist_name(&hashed_wasm_name),
+            ts_output: ts_output.as_deref().map(dist_name),
+            loader_shim_output: hashed_loader_name.as_deref().map(dist_name),
             type_: self.app_type,
+            bindgen_target: self.bindgen_target,
+            global_name,
+            skipped: if bindgen_unchanged {
+                vec!["wasm-bindgen"]
+            } else {
+                vec![]
+            },
         })
     }
 
+    /// Pre-initialize the final WASM module with wizer, by running its `wizer.initialize` export
+    /// at build time and snapshotting the resulting state.
+    #[tracing::instrument(level = "trace", skip(self, hashed_name))]
+    async fn wizer_build(&self, hashed_name: &str) -> Result<()> {
+        let version = self.cfg.tools.wizer.as_deref();
+        let wizer = tools::get(Application::Wizer, version).await?;
+
+        // Ensure our output dir is in place.
+        let wizer_name = Application::Wizer.name();
+        let mode_segment = if self.cfg.release { "release" } else { "debug" };
+        let output = self
+            .manifest
+            .metadata
+            .target_directory
+            .join(wizer_name)
+            .join(&self.cfg.cargo_target)
+            .join(mode_segment)
+            .join(hashed_name);
+        // The WASM file may be placed in a subdir of the dist dir, which is mirrored here.
+        let output_dir = output.parent().context("invalid wizer output path")?;
+        fs::create_dir_all(output_dir)
+            .await
+            .context("error creating wizer output dir")?;
+
+        // Build up args for calling wizer.
+        let arg_output = format!("--output={}", output);
+        let target_wasm = self
+            .cfg
+            .staging_dist
+            .join(hashed_name)
+            .to_string_lossy()
+            .to_string();
+        let args = [target_wasm.as_str(), &arg_output];
+
+        // Invoke wizer.
+        tracing::info!("calling wizer");
+        common::run_command(wizer_name, &wizer, &args)
+            .await
+            .map_err(|err| check_target_not_found_err(err, wizer_name))?;
+
+        // Copy the pre-initialized WASM file to the dist dir.
+        tracing::info!("copying generated wizer artifacts");
+        fs::copy(&output, self.cfg.staging_dist.join(hashed_name))
+            .await
+            .context("error copying wasm file to dist dir")?;
+
+        Ok(())
+    }
+
     #[tracing::instrument(level = "trace", skip(self, hashed_name))]
     async fn wasm_opt_build(&self, hashed_name: &str) -> Result<()> {
-        // If not in release mode, we skip calling wasm-opt.
-        if !self.cfg.release {
+        // If wasm-opt is disabled with `--no-opt`, we skip calling it.
+        if self.cfg.no_opt {
             return Ok(());
         }
 
         // If opt level is off, we skip calling wasm-opt as it wouldn't have any effect.
-        if self.wasm_opt == WasmOptLevel::Off {
+        if !self.wasm_opt.is_enabled() {
             return Ok(());
         }
 
-        let version = self.cfg.tools.wasm_opt.as_deref();
-        let wasm_opt = tools::get(Application::WasmOpt, version).await?;
-
         // Ensure our output dir is in place.
         let wasm_opt_name = Application::WasmOpt.name();
         let mode_segment = if self.cfg.release { "release" } else { "debug" };
@@ -495,39 +1022,201 @@ impl RustApp {
             .metadata
             .target_directory
             .join(wasm_opt_name)
-            .join(mode_segment);
-        fs::create_dir_all(&output)
+            .join(&self.cfg.cargo_target)
+            .join(mode_segment)
+            .join(hashed_name)
+            .into_std_path_buf();
+        // The WASM file may be placed in a subdir of the dist dir, which is mirrored here.
+        let output_dir = output.parent().context("invalid wasm-opt output path")?;
+        fs::create_dir_all(output_dir)
             .await
             .context("error creating wasm-opt output dir")?;
 
-        // Build up args for calling wasm-opt.
-        let output = output.join(hashed_name);
-        let arg_output = format!("--output={}", output);
-        let arg_opt_level = format!("-O{}", self.wasm_opt.as_ref());
-        let target_wasm = self
-            .cfg
-            .staging_dist
-            .join(hashed_name)
-            .to_string_lossy()
-            .to_string();
-        let mut args: Vec<&str> = vec![&arg_output, &arg_opt_level, &target_wasm];
-
+        let target_wasm = self.cfg.staging_dist.join(hashed_name);
+        let mut extra_args = vec![];
         if self.reference_types {
-            args.push("--enable-reference-types");
+            extra_args.push("--enable-reference-types");
         }
-
-        // Invoke wasm-opt.
-        tracing::info!("calling wasm-opt");
-        common::run_command(wasm_opt_name, &wasm_opt, &args)
-            .await
-            .map_err(|err| check_target_not_found_err(err, wasm_opt_name))?;
+        let source_map = run_wasm_opt(
+            &self.cfg,
+            &self.wasm_opt,
+            &target_wasm,
+            &output,
+            &extra_args,
+        )
+        .await?;
 
         // Copy the generated WASM file to the dist dir.
         tracing::info!("copying generated wasm-opt artifacts");
-        fs::copy(output, self.cfg.staging_dist.join(hashed_name))
+        fs::copy(&output, self.cfg.staging_dist.join(hashed_name))
             .await
             .context("error copying wasm file to dist dir")?;
+        if let Some(source_map) = source_map {
+            fs::copy(
+                &source_map,
+                self.cfg.staging_dist.join(format!("{}.map", hashed_name)),
+            )
+            .await
+            .context("error copying source map to dist dir")?;
+        }
+        if self.wasm_opt.emit_wat {
+            fs::copy(
+                output.with_extension("wat"),
+                self.cfg
+                    .staging_dist
+                    .join(Path::new(hashed_name).with_extension("wat")),
+            )
+            .await
+            .context("error copying WAT file to dist dir")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The file in the wasm-bindgen output dir recording the fingerprint of the run which generated
+/// the output.
+const WASM_BINDGEN_FINGERPRINT_FILE: &str = ".wasm-bindgen-fingerprint";
+
+/// The SHA-256 of everything determining the output of wasm-bindgen: the input WASM, the
+/// wasm-bindgen binary, whose path contains its version, and the arguments.
+async fn wasm_bindgen_fingerprint(
+    wasm_bindgen: &Path,
+    wasm: &Path,
+    args: &[&str],
+) -> Result<String> {
+    let wasm = fs::read(wasm)
+        .await
+        .context("error reading wasm file for the wasm-bindgen fingerprint")?;
+    let mut hasher = Sha256::new();
+    hasher.update(&wasm);
+    hasher.update(wasm_bindgen.to_string_lossy().as_bytes());
+    for arg in args {
+        hasher.update([0]);
+        hasher.update(arg.as_bytes());
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The files generated by a wasm-bindgen invocation.
+///
+/// As the generated files vary with the flags and version of wasm-bindgen, they are collected
+/// from its output dir instead of assuming their names.
+struct WasmBindgenOutput {
+    /// All generated `.wasm`, `.js` and `.ts` (including `.d.ts`) files.
+    files: Vec<PathBuf>,
+}
+
+impl WasmBindgenOutput {
+    /// File extensions of the generated files to collect.
+    const EXTENSIONS: [&'static str; 3] = ["wasm", "js", "ts"];
+
+    /// Collect the generated files from the given wasm-bindgen output dir.
+    async fn read(out_dir: &Path) -> Result<Self> {
+        let mut files = vec![];
+        let mut entries = fs::read_dir(out_dir)
+            .await
+            .context("error reading wasm-bindgen output dir")?;
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .context("error reading contents of wasm-bindgen output dir")?
+        {
+            let path = entry.path();
+            let is_generated = path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .map(|ext| Self::EXTENSIONS.contains(&ext))
+                .unwrap_or(false);
+            if is_generated && entry.file_type().await?.is_file() {
+                files.push(path);
+            }
+        }
+        files.sort();
+
+        Ok(Self { files })
+    }
+
+    /// Check whether a file with the given name was generated.
+    fn contains(&self, file_name: &str) -> bool {
+        self.files.iter().any(|path| {
+            path.file_name()
+                .map(|name| name == file_name)
+                .unwrap_or(false)
+        })
+    }
+}
 
+/// Check whether the project is a WASM component model project, to be built with
+/// `cargo component`. This is the case if the package has `[package.metadata.component]` in its
+/// `Cargo.toml`, or a `wit` dir next to it.
+async fn is_component_project(manifest: &CargoMetadata) -> Result<bool> {
+    if manifest.package.metadata.get("component").is_some() {
+        return Ok(true);
+    }
+    match Path::new(&manifest.manifest_path).parent() {
+        Some(dir) => Ok(fs::metadata(dir.join("wit"))
+            .await
+            .map(|meta| meta.is_dir())
+            .unwrap_or(false)),
+        None => Ok(false),
+    }
+}
+
+/// A pipeline for WASM components built with `cargo component`, taking the place of wasm-bindgen
+/// for component model projects.
+///
+/// The component is copied to the dist dir, and the WIT world it implements is extracted next to
+/// it with `wasm-tools component wit`, which also makes sure the output is a valid component.
+struct ComponentModelBuildPipeline {
+    /// The ID of the source HTML element.
+    id: Option<usize>,
+    /// Runtime config.
+    cfg: Arc<RtcBuild>,
+}
+
+impl ComponentModelBuildPipeline {
+    #[tracing::instrument(level = "trace", skip(self, wasm, hashed_name))]
+    async fn run(&self, wasm: &Path, hashed_name: &str) -> Result<RustComponentOutput> {
+        let wasm_output = format!("{}.wasm", hashed_name);
+        let wit_output = format!("{}.wit", hashed_name);
+        let wasm_path_dist = self.cfg.staging_dist.join(wasm_output);
+        fs::copy(wasm, &wasm_path_dist)
+            .await
+            .context("error copying WASM component to stage dir")?;
+
+        let version = self.cfg.tools.wasm_tools.as_deref();
+        let wasm_tools = tools::get(Application::WasmTools, version).await?;
+        let wasm_tools_name = Application::WasmTools.name();
+
+        let target_wasm = wasm_path_dist.to_string_lossy().to_string();
+        let arg_output = format!(
+            "--output={}",
+            self.cfg.staging_dist.join(wit_output).display()
+        );
+        let args = ["component", "wit", &target_wasm, &arg_output];
+
+        tracing::info!("calling wasm-tools");
+        common::run_command(wasm_tools_name, &wasm_tools, &args)
+            .await
+            .map_err(|err| check_target_not_found_err(err, wasm_tools_name))?;
+
+        Ok(RustComponentOutput { id: self.id })
+    }
+}
+
+/// The output of a WASM component model build pipeline.
+pub struct RustComponentOutput {
+    /// The ID of this pipeline.
+    pub id: Option<usize>,
+}
+
+impl RustComponentOutput {
+    pub async fn finalize(self, dom: &mut Document) -> Result<()> {
+        // Components can't be loaded by the browser directly, so only the link tag is removed.
+        if let Some(id) = self.id {
+            dom.select(&super::trunk_id_selector(id)).remove();
+        }
         Ok(())
     }
 }
@@ -542,7 +1231,16 @@ fn find_wasm_bindgen_version<'a>(
     cfg: &'a ConfigOptsTools,
     manifest: &CargoMetadata,
 ) -> Option<Cow<'a, str>> {
-    let find_lock = || -> Option<Cow<'_, str>> {
+    cfg.wasm_bindgen
+        .as_deref()
+        .map(Cow::from)
+        .or_else(|| find_wasm_bindgen_crate_version(manifest).map(Cow::from))
+}
+
+/// Find the version of the `wasm-bindgen` crate the project depends on, either from the
+/// `Cargo.lock` or the `Cargo.toml`.
+fn find_wasm_bindgen_crate_version(manifest: &CargoMetadata) -> Option<String> {
+    let find_lock = || -> Option<String> {
         let lock_path = Path::new(&manifest.manifest_path)
             .parent()?
             .join("Cargo.lock");
@@ -553,23 +1251,72 @@ fn find_wasm_bindgen_version<'a>(
             .packages
             .into_iter()
             .find(|p| p.name == name)
-            .map(|p| Cow::from(p.version.to_string()))
+            .map(|p| p.version.to_string())
     };
 
-    let find_manifest = || -> Option<Cow<'_, str>> {
+    let find_manifest = || -> Option<String> {
         manifest
             .metadata
             .packages
             .iter()
             .find(|p| p.name == "wasm-bindgen")
-            .map(|p| Cow::from(p.version.to_string()))
+            .map(|p| p.version.to_string())
     };
 
-    cfg.wasm_bindgen
-        .as_deref()
-        .map(Cow::from)
-        .or_else(find_lock)
-        .or_else(find_manifest)
+    find_lock().or_else(find_manifest)
+}
+
+/// Known-compatible pairs of `(cli_version, crate_version)` for wasm-bindgen, where the versions
+/// are not identical.
+///
+/// The wasm-bindgen CLI generally requires the exact version of the `wasm-bindgen` crate used by
+/// the project. Only pairs exercised by the CI matrix, which builds the examples with the default
+/// CLI version against the crate versions of their `Cargo.lock`, belong in here. It currently
+/// builds all examples with matching versions.
+const COMPATIBLE_VERSIONS: &[(&str, &str)] = &[];
+
+/// Warn if the configured wasm-bindgen CLI version, which may be a semver range, does not match
+/// the version of the `wasm-bindgen` crate, suggesting known-compatible CLI versions where
+/// possible.
+fn check_wasm_bindgen_version(cli_version: &str, crate_version: &str) {
+    let compatible = |(cli, krate): &(&str, &str)| {
+        *krate == crate_version && tools::version_matches(cli_version, cli)
+    };
+    if tools::version_matches(cli_version, crate_version)
+        || COMPATIBLE_VERSIONS.iter().any(compatible)
+    {
+        return;
+    }
+
+    let suggestions = compatible_cli_versions(COMPATIBLE_VERSIONS, crate_version);
+    if suggestions.is_empty() {
+        tracing::warn!(
+            "wasm-bindgen CLI version {} does not match the version of the wasm-bindgen crate {}, \
+             consider using CLI version {}",
+            cli_version,
+            crate_version,
+            crate_version
+        );
+    } else {
+        tracing::warn!(
+            "wasm-bindgen CLI version {} does not match the version of the wasm-bindgen crate {}, \
+             you could use CLI version {} with crate version {}",
+            cli_version,
+            crate_version,
+            suggestions.join(" or "),
+            crate_version
+        );
+    }
+}
+
+/// Find all CLI versions of the given table that are compatible with the crate version, besides
+/// the identical version.
+fn compatible_cli_versions<'a>(table: &[(&'a str, &str)], crate_version: &str) -> Vec<&'a str> {
+    table
+        .iter()
+        .filter(|(_, krate)| *krate == crate_version)
+        .map(|(cli, _)| *cli)
+        .collect()
 }
 
 /// The output of a cargo build pipeline.
@@ -588,6 +1335,12 @@ pub struct RustAppOutput {
     pub loader_shim_output: Option<String>,
     /// Is this module main or a worker.
     pub type_: RustAppType,
+    /// The target wasm-bindgen generated the bindings for.
+    pub bindgen_target: BindgenTarget,
+    /// The name of the global defined by the `no-modules` bindings.
+    pub global_name: String,
+    /// The steps skipped as their input was unchanged since the previous build.
+    pub skipped: Vec<&'static str>,
 }
 
 pub fn pattern_evaluate(template: &str, params: &HashMap<String, String>) -> String {
@@ -615,6 +1368,17 @@ impl RustAppOutput {
             }
             return Ok(());
         }
+        if !matches!(
+            self.bindgen_target,
+            BindgenTarget::Web | BindgenTarget::NoModules
+        ) {
+            // Bindings for bundlers and other runtimes can't be loaded by the browser directly, so
+            // only the generated files are provided.
+            if let Some(id) = self.id {
+                dom.select(&super::trunk_id_selector(id)).remove();
+            }
+            return Ok(());
+        }
 
         let (base, js, wasm, head, body) = (
             &self.cfg.public_url,
@@ -635,6 +1399,16 @@ impl RustAppOutput {
 
         let preload = match pattern_preload {
             Some(pattern) => pattern_evaluate(pattern, &params),
+            None if self.bindgen_target == BindgenTarget::NoModules => {
+                format!(
+                    r#"
+<link rel="preload" href="{base}{wasm}" as="fetch" type="application/wasm" crossorigin>
+<link rel="preload" href="{base}{js}" as="script">"#,
+                    base = base,
+                    js = js,
+                    wasm = wasm
+                )
+            }
             None => {
                 format!(
                     r#"
@@ -650,6 +1424,15 @@ impl RustAppOutput {
 
         let script = match pattern_script {
             Some(pattern) => pattern_evaluate(pattern, &params),
+            None if self.bindgen_target == BindgenTarget::NoModules => {
+                format!(
+                    r#"<script src="{base}{js}"></script><script>{global}('{base}{wasm}');</script>"#,
+                    base = base,
+                    js = js,
+                    wasm = wasm,
+                    global = self.global_name,
+                )
+            }
             None => {
                 format!(
                     r#"<script type="module">import init from '{base}{js}';init('{base}{wasm}');</script>"#,
@@ -669,8 +1452,626 @@ impl RustAppOutput {
     }
 }
 
+/// Generate a `package.json` for publishing the wasm-bindgen output as npm package, using the name
+/// and version of the crate.
+fn package_json(
+    package: &cargo_metadata::Package,
+    js: &str,
+    ts: Option<&str>,
+) -> serde_json::Value {
+    let mut package_json = serde_json::json!({
+        "name": package.name,
+        "version": package.version.to_string(),
+        "main": js,
+        "module": js,
+    });
+    if let Some(ts) = ts {
+        package_json["types"] = ts.into();
+    }
+    package_json
+}
+
+/// Whether wasm-bindgen keeps the debug info without `data-keep-debug`, which is the case for
+/// debug builds, and when `RUST_LOG` mentions `wasm`, to keep the function names for profiling
+/// in the browser's devtools.
+fn keep_debug_by_default(cfg: &RtcBuild) -> bool {
+    !cfg.release
+        || std::env::var("RUST_LOG")
+            .map(|log| log.contains("wasm"))
+            .unwrap_or(false)
+}
+
+/// Settings for the wasm-opt invocation, configured through the `data-wasm-opt*` attributes, or
+/// for a cargo profile through `wasm_opt_profiles` in `Trunk.toml`.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WasmOptConfig {
+    /// An optional optimization setting that enables wasm-opt. Can be nothing, `0` (default), `1`,
+    /// `2`, `3`, `4`, `s or `z`. Using `0` disables wasm-opt completely.
+    level: WasmOptLevel,
+    /// Preserve debug info in the optimized output, for builds that need to be optimized but still
+    /// debuggable (like staging or preview builds).
+    preserve_debug_info: bool,
+    /// Assume the low 1K of memory is not used by the application, which holds for WASM linked
+    /// by rustc as its data starts at a higher address.
+    low_memory_unused: bool,
+    /// Assume imported memory is zero-initialized, which holds for the memory created by the
+    /// wasm-bindgen JS glue code.
+    zero_filled_memory: bool,
+    /// Strip the DWARF debug info, unless it is preserved explicitly. Enabled in release mode,
+    /// otherwise the debug info is kept through the optimization passes.
+    strip_debug: bool,
+    /// Strip the producers section, which reveals the toolchain versions used to build the app.
+    strip_producers: bool,
+    /// The URL of the source map to embed in the output.
+    source_map_url: Option<String>,
+    /// Also write the output in the WebAssembly text format, to a `.wat` file next to it.
+    emit_wat: bool,
+    /// Log the metrics of the output, like the number of functions, and add them to
+    /// `wasm-opt-metrics.json` in the dist dir.
+    emit_metrics: bool,
+    /// Whether to map the source map of the input, `{input}.map`, to the output. Used if it
+    /// exists when unset, required when enabled.
+    preserve_source_maps: Option<bool>,
+    /// Arguments for individual passes, as pairs of the argument name and its value.
+    #[serde(skip)]
+    pass_args: Vec<(String, String)>,
+    /// The WASM proposals wasm-opt may assume to be supported by the runtime.
+    features: WasmFeatures,
+    /// Skip wasm-opt for WASM files smaller than this number of bytes.
+    min_size_bytes: Option<u64>,
+    /// Generate and optimize the stack IR, which takes noticeably longer for large binaries.
+    stack_ir: bool,
+    /// Run the Asyncify pass, allowing synchronous code to pause and resume on async imports.
+    asyncify: bool,
+    /// The imports which may pause the execution, like `env.sleep`. All imports are assumed to
+    /// possibly pause if empty.
+    asyncify_imports: Vec<String>,
+    /// Assume that no import pauses the execution, apart from indirect calls.
+    asyncify_ignore_imports: bool,
+    /// Assume that no imports or exports are added at runtime, allowing more dead code to be
+    /// eliminated. A wrong assumption causes crashes at runtime, not errors at build time.
+    closed_world: bool,
+    /// Run the dead code elimination pass after the optimization passes of the level.
+    dce: bool,
+    /// Run the memory packing pass, rearranging the data segments for a smaller and better
+    /// compressible binary. Enabled in release mode.
+    memory_packing: bool,
+    /// Run the experimental Souperify pass, emitting the IR of the module for the Souper
+    /// super-optimizer.
+    souperify: bool,
+    /// Name the types of the module by their structure, for more meaningful type names in
+    /// debuggers. Enabled when debug info is preserved, if unset.
+    name_types: Option<bool>,
+    /// Run the constant hoisting pass after the optimization passes of the level, moving repeated
+    /// constants into globals.
+    const_hoisting: bool,
+    /// Warn if constant hoisting adds more globals than `new_globals_threshold`, as some
+    /// embedders limit the number of globals.
+    warn_on_new_globals: bool,
+    /// The number of globals constant hoisting may add without a warning, 100 if unset.
+    new_globals_threshold: Option<u64>,
+}
+
+/// WASM proposals to enable in wasm-opt, each configured through a
+/// `data-wasm-opt-enable-{proposal}` flag.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct WasmFeatures {
+    /// Non-trapping float-to-int conversions, as emitted by rustc when targeting current browsers.
+    nontrapping_float_to_int: bool,
+    /// Garbage collection, which builds upon reference types.
+    gc: bool,
+    /// Functions returning multiple values, like Rust tuples. Enabled when the WASM binary uses
+    /// them.
+    multivalue: bool,
+    /// Bulk memory operations like `memory.copy`, as emitted by rustc by default for recent
+    /// versions. Enabled when the WASM binary uses them.
+    bulk_memory: bool,
+    /// Strings as references to host strings, which builds upon GC and reference types.
+    strings: bool,
+}
+
+impl WasmFeatures {
+    /// The first binaryen version supporting the GC proposal. As the strings proposal enables GC,
+    /// this is the first version usable with strings as well.
+    const GC_MIN_VERSION: u32 = 113;
+
+    /// Arguments enabling the features in wasm-opt, including the features they depend on.
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if self.nontrapping_float_to_int {
+            args.push("--enable-nontrapping-float-to-int".into());
+        }
+        if self.gc {
+            args.push("--enable-reference-types".into());
+            args.push("--enable-gc".into());
+        }
+        if self.multivalue {
+            args.push("--enable-multivalue".into());
+        }
+        if self.bulk_memory {
+            args.push("--enable-bulk-memory".into());
+        }
+        if self.strings {
+            args.push("--enable-strings".into());
+        }
+        args
+    }
+
+    /// Ensure the given wasm-opt version, like `version_113`, supports all enabled features.
+    ///
+    /// Versions not following binaryen's naming scheme are assumed to be supported.
+    fn check_version(&self, version: &str) -> Result<()> {
+        if let Some(number) = binaryen_version_number(version) {
+            ensure!(
+                !self.gc || number >= Self::GC_MIN_VERSION,
+                "`data-wasm-opt-enable-gc`, which is also enabled by \
+                 `data-wasm-opt-enable-strings`, requires wasm-opt version_{} or newer, but {} is \
+                 configured; set a newer version with `wasm_opt` in the `[tools]` section of \
+                 `Trunk.toml`",
+                Self::GC_MIN_VERSION,
+                version
+            );
+        }
+        Ok(())
+    }
+}
+
+/// The number of a binaryen version like `version_113`, if it follows binaryen's naming scheme.
+fn binaryen_version_number(version: &str) -> Option<u32> {
+    version
+        .strip_prefix("version_")
+        .and_then(|number| number.split(|c: char| !c.is_ascii_digit()).next())
+        .and_then(|number| number.parse().ok())
+}
+
+impl WasmOptConfig {
+    /// The first binaryen version supporting `--closed-world`.
+    const CLOSED_WORLD_MIN_VERSION: u32 = 112;
+    /// The first binaryen version known to provide the Souperify pass.
+    const SOUPERIFY_MIN_VERSION: u32 = 100;
+    /// The first binaryen version whose memory packing keeps the passive data segments and
+    /// their `memory.init` instructions of wasm-bindgen's output for threads intact.
+    const MEMORY_PACKING_BINDGEN_MIN_VERSION: u32 = 101;
+    /// The number of globals constant hoisting may add without a warning, unless configured.
+    const DEFAULT_NEW_GLOBALS_THRESHOLD: u64 = 100;
+
+    /// A config that disables wasm-opt.
+    fn off() -> Self {
+        Self {
+            level: WasmOptLevel::Off,
+            ..Default::default()
+        }
+    }
+
+    /// The default config for the given cargo profile.
+    ///
+    /// The `release` and `bench` profiles use wasm-opt's default level, which is equivalent to
+    /// `-Os`, and strip the debug info. The `dev` and `test` profiles use `-O1`, keeping the debug
+    /// info. Other profiles disable wasm-opt. If `CARGO_PROFILE_<NAME>_OPT_LEVEL` selects a size
+    /// focused level for the profile, the matching wasm-opt level is used instead.
+    pub(super) fn from_cargo_profile(profile: &str) -> WasmOptConfig {
+        let (level, release) = match profile {
+            "release" | "bench" => (WasmOptLevel::Default, true),
+            "dev" | "debug" | "test" => (WasmOptLevel::One, false),
+            _ => (WasmOptLevel::Off, false),
+        };
+        let opt_level = std::env::var(format!(
+            "CARGO_PROFILE_{}_OPT_LEVEL",
+            profile.to_uppercase().replace('-', "_")
+        ));
+        let level = match opt_level.as_deref() {
+            Ok("s") if level != WasmOptLevel::Off => WasmOptLevel::S,
+            Ok("z") if level != WasmOptLevel::Off => WasmOptLevel::Z,
+            _ => level,
+        };
+        Self {
+            level,
+            strip_debug: release,
+            memory_packing: release,
+            ..Default::default()
+        }
+    }
+
+    /// The config for the given cargo profile, as configured in `wasm_opt_profiles`, falling back
+    /// to the default config of the profile, see [`WasmOptConfig::from_cargo_profile`].
+    ///
+    /// Custom profiles which are not configured use the config of the `release` profile if they
+    /// inherit from it, or if building in release mode.
+    pub(super) fn for_profile(
+        profile: &str,
+        profiles: &HashMap<String, WasmOptConfig>,
+        inherits_release: bool,
+    ) -> Self {
+        if let Some(config) = profiles.get(profile) {
+            tracing::debug!("using the wasm-opt config of the `{}` profile", profile);
+            return config.clone();
+        }
+        if BUILTIN_CARGO_PROFILES.contains(&profile) {
+            return Self::from_cargo_profile(profile);
+        }
+        if inherits_release {
+            tracing::info!(
+                "using the wasm-opt config of the `release` profile for the custom cargo profile \
+                 `{}`, which is not configured in `wasm_opt_profiles`",
+                profile
+            );
+            Self::from_cargo_profile("release")
+        } else {
+            tracing::info!(
+                "wasm-opt is disabled for the custom cargo profile `{}`, configure it in \
+                 `wasm_opt_profiles` to enable it",
+                profile
+            );
+            Self::from_cargo_profile(profile)
+        }
+    }
+
+    /// Read the config from the attributes of a `<link data-trunk rel="rust|wasm-opt" .../>`
+    /// element, on top of the config of the cargo profile, see [`WasmOptConfig::for_profile`].
+    ///
+    /// Without an explicit `data-wasm-opt` level, the level of the selected preset is used in
+    /// release mode, falling back to the level of the profile.
+    ///
+    /// The closed world assumption holds for the output of wasm-bindgen, as all imports and
+    /// exports are known to its JS glue code, so it is enabled by default for it. Other WASM files
+    /// have to opt in.
+    pub(super) fn from_attrs(
+        attrs: &Attrs,
+        release: bool,
+        profile: WasmOptConfig,
+        wasm_bindgen: bool,
+    ) -> Result<Self> {
+        let preset: Option<WasmOptPreset> = attrs
+            .get("data-wasm-opt-preset")
+            .map(|val| val.parse())
+            .transpose()?;
+        let level = attrs
+            .get("data-wasm-opt")
+            .map(|val| val.parse())
+            .transpose()?
+            .or_else(|| preset.filter(|_| release).map(|preset| preset.level()))
+            .unwrap_or(profile.level);
+        let flag = |name: &str| {
+            attrs.contains_key(&format!("data-wasm-opt-{}", name))
+                || preset
+                    .map(|preset| preset.flags().contains(&name))
+                    .unwrap_or(false)
+        };
+
+        let preserve_debug_info = flag("preserve-debug-info") || profile.preserve_debug_info;
+        let mut config = Self {
+            level,
+            preserve_debug_info,
+            low_memory_unused: flag("low-memory-unused") || profile.low_memory_unused,
+            zero_filled_memory: flag("zero-filled-memory") || profile.zero_filled_memory,
+            strip_debug: profile.strip_debug && !preserve_debug_info,
+            strip_producers: flag("strip-producers") || profile.strip_producers,
+            stack_ir: flag("stack-ir") || profile.stack_ir,
+            asyncify: flag("asyncify") || profile.asyncify,
+            asyncify_imports: attrs
+                .get("data-wasm-opt-asyncify-imports")
+                .map(|val| {
+                    val.split(',')
+                        .map(str::trim)
+                        .filter(|import| !import.is_empty())
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .unwrap_or(profile.asyncify_imports),
+            asyncify_ignore_imports: flag("asyncify-ignore-imports")
+                || profile.asyncify_ignore_imports,
+            closed_world: (wasm_bindgen || flag("closed-world") || profile.closed_world)
+                && !flag("open-world"),
+            dce: flag("dce") || profile.dce,
+            memory_packing: attrs
+                .get("data-wasm-opt-memory-packing")
+                .map(|val| val != "false")
+                .unwrap_or(profile.memory_packing),
+            souperify: flag("souperify") || profile.souperify,
+            name_types: attrs
+                .get("data-wasm-opt-name-types")
+                .map(|val| val != "false")
+                .or(profile.name_types),
+            const_hoisting: flag("const-hoisting") || profile.const_hoisting,
+            warn_on_new_globals: flag("warn-on-new-globals") || profile.warn_on_new_globals,
+            new_globals_threshold: attrs
+                .get("data-wasm-opt-new-globals-threshold")
+                .map(|val| {
+                    val.parse().with_context(|| {
+                        format!(
+                            "`data-wasm-opt-new-globals-threshold` must be a number of globals, \
+                             got `{}`",
+                            val
+                        )
+                    })
+                })
+                .transpose()?
+                .or(profile.new_globals_threshold),
+            source_map_url: attrs
+                .get("data-wasm-opt-source-map-url")
+                .cloned()
+                .or(profile.source_map_url),
+            emit_wat: flag("emit-wat") || profile.emit_wat,
+            emit_metrics: flag("emit-metrics") || profile.emit_metrics,
+            preserve_source_maps: attrs
+                .get("data-wasm-opt-preserve-source-maps")
+                .map(|val| val != "false")
+                .or(profile.preserve_source_maps),
+            pass_args: attrs
+                .get("data-wasm-opt-pass-args")
+                .map(|val| parse_wasm_opt_pass_args(val))
+                .transpose()?
+                .unwrap_or(profile.pass_args),
+            features: WasmFeatures {
+                nontrapping_float_to_int: flag("enable-nontrapping-float-to-int")
+                    || profile.features.nontrapping_float_to_int,
+                gc: flag("enable-gc") || profile.features.gc,
+                multivalue: flag("enable-multivalue") || profile.features.multivalue,
+                bulk_memory: flag("enable-bulk-memory") || profile.features.bulk_memory,
+                strings: flag("enable-strings") || profile.features.strings,
+            },
+            min_size_bytes: attrs
+                .get("data-wasm-opt-min-size")
+                .map(|val| {
+                    val.parse().with_context(|| {
+                        format!(
+                            "`data-wasm-opt-min-size` must be a number of bytes, got `{}`",
+                            val
+                        )
+                    })
+                })
+                .transpose()?
+                .or(profile.min_size_bytes),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that the settings can be combined, enabling the features required by the enabled
+    /// features.
+    fn validate(&mut self) -> Result<()> {
+        ensure!(
+            self.asyncify || (self.asyncify_imports.is_empty() && !self.asyncify_ignore_imports),
+            "`data-wasm-opt-asyncify-imports` and `data-wasm-opt-asyncify-ignore-imports` require \
+             `data-wasm-opt-asyncify`"
+        );
+        ensure!(
+            self.asyncify_imports.is_empty() || !self.asyncify_ignore_imports,
+            "`data-wasm-opt-asyncify-imports` can not be combined with \
+             `data-wasm-opt-asyncify-ignore-imports`"
+        );
+        ensure!(
+            self.const_hoisting || !self.warn_on_new_globals,
+            "`data-wasm-opt-warn-on-new-globals` requires `data-wasm-opt-const-hoisting`"
+        );
+
+        // Flattening the IR is not able to keep the DWARF sections up to date, so wasm-opt drops
+        // them instead.
+        if self.preserve_debug_info && self.level == WasmOptLevel::Four {
+            tracing::warn!(
+                "wasm-opt level `4` flattens the IR which silently drops DWARF debug info, despite \
+                 `data-wasm-opt-preserve-debug-info` being set"
+            );
+        }
+
+        // Strings are references to GC objects.
+        if self.features.strings && !self.features.gc {
+            tracing::debug!(
+                "enabling the GC and reference types proposals, as the strings proposal builds \
+                 upon them"
+            );
+            self.features.gc = true;
+        }
+        Ok(())
+    }
+
+    /// The URL of the source map to generate for the output file of the given name, if any.
+    ///
+    /// Without an explicit URL, a source map is still generated when debug info is preserved,
+    /// using the relative URL `{file_name}.map`.
+    fn source_map_url(&self, file_name: &str) -> Option<String> {
+        self.source_map_url.clone().or_else(|| {
+            self.preserve_debug_info
+                .then(|| format!("{}.map", file_name))
+        })
+    }
+
+    /// Enable the WASM proposals the binary to optimize is known to use. Otherwise wasm-opt
+    /// rejects or lowers the instructions of these proposals, which rustc generated on purpose.
+    pub(super) fn detect_features(&mut self, features: &TargetFeatures) {
+        if features.contains("multivalue") {
+            self.features.multivalue = true;
+        }
+        if features.contains("bulk-memory") {
+            self.features.bulk_memory = true;
+        }
+    }
+
+    /// Whether wasm-opt is enabled at all.
+    pub(super) fn is_enabled(&self) -> bool {
+        self.level != WasmOptLevel::Off
+    }
+
+    /// Arguments for the wasm-opt invocation, apart from the input and output files.
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        // wasm-opt runs the passes in the given order, and Asyncify output benefits a lot from
+        // being optimized afterwards.
+        if self.asyncify {
+            args.push("--asyncify".into());
+        }
+        args.push(format!("-O{}", self.level.as_ref()));
+        if self.dce {
+            args.push("--dce".into());
+        }
+        if self.const_hoisting {
+            args.push("--const-hoisting".into());
+        }
+        // Types are named after the optimizations, which may merge or remove them.
+        if self.name_types.unwrap_or(self.preserve_debug_info) {
+            args.push("--name-types".into());
+        }
+        if self.preserve_debug_info || !self.strip_debug {
+            args.push("--debuginfo".into());
+        }
+        if self.low_memory_unused {
+            args.push("--low-memory-unused".into());
+        }
+        if self.zero_filled_memory {
+            args.push("--zero-filled-memory".into());
+        }
+        if self.strip_debug {
+            args.push("--strip-debug".into());
+        }
+        if self.strip_producers {
+            args.push("--strip-producers".into());
+        }
+        // Optimizing the stack IR requires generating it first. Multi-value functions benefit
+        // particularly from it.
+        if self.stack_ir || self.features.multivalue {
+            args.push("--generate-stack-ir".into());
+            args.push("--optimize-stack-ir".into());
+        }
+        // The Asyncify settings are arguments of the pass, not flags of wasm-opt itself.
+        if !self.asyncify_imports.is_empty() {
+            args.push(format!(
+                "--pass-arg=asyncify-imports@{}",
+                self.asyncify_imports.join(",")
+            ));
+        }
+        if self.asyncify_ignore_imports {
+            args.push("--pass-arg=asyncify-ignore-imports".into());
+        }
+        args.extend(
+            self.pass_args
+                .iter()
+                .map(|(name, value)| format!("--pass-arg={}@{}", name, value)),
+        );
+        args.extend(self.features.args());
+        args
+    }
+}
+
+/// Parse the value of `data-wasm-opt-pass-args`, a space separated list of pass arguments in
+/// wasm-opt's `name@value` form. Values may contain commas, as used for lists of imports.
+///
+/// The arguments are handed to wasm-opt as separate process arguments and never pass through a
+/// shell, the validation only catches arguments that wasm-opt would misinterpret.
+fn parse_wasm_opt_pass_args(val: &str) -> Result<Vec<(String, String)>> {
+    val.split_whitespace()
+        .map(|arg| {
+            let (name, value) = arg.split_once('@').with_context(|| {
+                format!(
+                    "wasm-opt pass argument `{}` must be of the form `name@value`",
+                    arg
+                )
+            })?;
+            ensure!(
+                !name.is_empty()
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+                "invalid wasm-opt pass argument name `{}`",
+                name
+            );
+            ensure!(
+                !value.chars().any(char::is_control),
+                "invalid value for wasm-opt pass argument `{}`",
+                name
+            );
+            Ok((name.to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+/// A pool limiting the number of concurrently running wasm-opt processes.
+///
+/// It is shared by all Rust app pipelines of a build, including the builds for multiple targets, as
+/// a single wasm-opt process may already use a lot of CPU and memory for larger applications.
+#[derive(Clone, Debug)]
+pub struct WasmOptPool {
+    /// The permits for running a wasm-opt process.
+    semaphore: Arc<Semaphore>,
+    /// The maximum number of wasm-opt processes running at the same time.
+    max_concurrent: usize,
+}
+
+impl WasmOptPool {
+    /// Create a new pool, running up to `max_concurrent` wasm-opt processes at once.
+    pub fn new(max_concurrent: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrent)),
+            max_concurrent,
+        }
+    }
+
+    /// Wait for a free slot in the pool. The slot is freed again when the permit is dropped.
+    async fn acquire(&self) -> Result<SemaphorePermit<'_>> {
+        if self.semaphore.available_permits() == 0 {
+            tracing::info!(
+                "waiting for one of {} running wasm-opt processes to finish",
+                self.max_concurrent
+            );
+        }
+        self.semaphore
+            .acquire()
+            .await
+            .context("error acquiring a wasm-opt slot")
+    }
+}
+
+/// Presets of wasm-opt settings, selected with `data-wasm-opt-preset`.
+///
+/// A preset provides the default optimization level and enables a set of `data-wasm-opt-*` flags.
+/// Setting `data-wasm-opt` or any of the flags explicitly still takes effect on top of a preset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WasmOptPreset {
+    /// Focus on a small binary size.
+    Size,
+    /// Wasm-opt's default optimizations, assuming the WASM proposals supported by all current
+    /// browsers.
+    Release,
+    /// Spend more time on optimizations, including those of the stack IR.
+    Aggressive,
+}
+
+impl WasmOptPreset {
+    /// The optimization level of the preset.
+    fn level(&self) -> WasmOptLevel {
+        match self {
+            Self::Size => WasmOptLevel::Z,
+            Self::Release => WasmOptLevel::Default,
+            Self::Aggressive => WasmOptLevel::Three,
+        }
+    }
+
+    /// The names of the `data-wasm-opt-*` flags enabled by the preset.
+    fn flags(&self) -> &'static [&'static str] {
+        match self {
+            Self::Size => &["low-memory-unused", "zero-filled-memory", "strip-producers"],
+            Self::Release => &["enable-nontrapping-float-to-int", "dce"],
+            Self::Aggressive => &["enable-nontrapping-float-to-int", "stack-ir"],
+        }
+    }
+}
+
+impl FromStr for WasmOptPreset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "size" => Self::Size,
+            "release" => Self::Release,
+            "aggressive" => Self::Aggressive,
+            _ => bail!("unknown wasm-opt preset `{}`", s),
+        })
+    }
+}
+
 /// Different optimization levels that can be configured with `wasm-opt`.
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum WasmOptLevel {
     /// Default optimization passes.
     Default,
@@ -724,12 +2125,489 @@ impl AsRef<str> for WasmOptLevel {
     }
 }
 
+impl<'de> Deserialize<'de> for WasmOptLevel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
 impl Default for WasmOptLevel {
     fn default() -> Self {
         Self::Default
     }
 }
 
+/// Run wasm-opt on the `input` WASM file with the given config, writing the result to `output`.
+///
+/// If a source map is generated, it is written next to the output, and its path is returned.
+pub(super) async fn run_wasm_opt(
+    cfg: &RtcBuild,
+    config: &WasmOptConfig,
+    input: &Path,
+    output: &Path,
+    extra_args: &[&str],
+) -> Result<Option<PathBuf>> {
+    ensure!(
+        !config.souperify || cfg.experimental_features,
+        "`data-wasm-opt-souperify` runs an experimental wasm-opt pass and requires \
+         `experimental_features = true` in the `[build]` section of `Trunk.toml`"
+    );
+    let before = fs::metadata(input)
+        .await
+        .context("error reading wasm file metadata")?
+        .len();
+    let version = cfg.tools.wasm_opt.as_deref();
+    if matches!(config.min_size_bytes, Some(min_size) if before < min_size) {
+        tracing::info!(
+            "skipping wasm-opt: binary is too small to benefit ({} bytes)",
+            before
+        );
+        // wasm-opt is only needed for the extra outputs, not for skipping it.
+        let wasm_opt = if config.emit_wat || config.emit_metrics {
+            Some(tools::get(Application::WasmOpt, version).await?)
+        } else {
+            None
+        };
+        skip_wasm_opt(cfg, config, wasm_opt.as_deref(), input, output).await?;
+        return Ok(None);
+    }
+
+    let version_or_default = version.unwrap_or_else(|| Application::WasmOpt.default_version());
+    config.features.check_version(version_or_default)?;
+    let wasm_opt = tools::get(Application::WasmOpt, version).await?;
+    let wasm_opt_name = Application::WasmOpt.name();
+
+    // Build up args for calling wasm-opt.
+    let arg_output = format!("--output={}", output.display());
+    let target_wasm = input.to_string_lossy().to_string();
+    let mut opt_args = config.args();
+    if config.closed_world {
+        // Older versions reject the unknown flag, so the assumption is dropped for them.
+        match binaryen_version_number(version_or_default) {
+            Some(number) if number < WasmOptConfig::CLOSED_WORLD_MIN_VERSION => {
+                tracing::debug!(
+                    "not passing --closed-world to wasm-opt {}, it requires version_{} or newer",
+                    version_or_default,
+                    WasmOptConfig::CLOSED_WORLD_MIN_VERSION
+                );
+            }
+            _ => opt_args.push("--closed-world".into()),
+        }
+    }
+    if config.memory_packing {
+        match binaryen_version_number(version_or_default) {
+            Some(number)
+                if number < WasmOptConfig::MEMORY_PACKING_BINDGEN_MIN_VERSION
+                    && Producers::read(input)
+                        .await?
+                        .processed_by("wasm-bindgen")
+                        .is_some() =>
+            {
+                tracing::warn!(
+                    "skipping the memory packing pass, wasm-opt {} may break the memory \
+                     initialization of wasm-bindgen's output, it requires version_{} or newer",
+                    version_or_default,
+                    WasmOptConfig::MEMORY_PACKING_BINDGEN_MIN_VERSION
+                );
+            }
+            _ => opt_args.push("--memory-packing".into()),
+        }
+    }
+    if config.souperify {
+        // The pass is missing from older versions, and was removed from some builds.
+        match binaryen_version_number(version_or_default) {
+            Some(number) if number < WasmOptConfig::SOUPERIFY_MIN_VERSION => {
+                tracing::warn!(
+                    "skipping the souperify pass, it is not available in wasm-opt {}, which is \
+                     older than version_{}",
+                    version_or_default,
+                    WasmOptConfig::SOUPERIFY_MIN_VERSION
+                );
+            }
+            _ => opt_args.push("--souperify".into()),
+        }
+    }
+    let mut args: Vec<&str> = vec![&arg_output, &target_wasm];
+    args.extend(opt_args.iter().map(String::as_str));
+    args.extend(extra_args);
+
+    // A source map of the input, like the ones emitted by Emscripten, is carried over to the
+    // output, next to the output file.
+    let input_source_map = input.with_file_name(format!(
+        "{}.map",
+        input
+            .file_name()
+            .context("wasm-opt input has no file name")?
+            .to_string_lossy()
+    ));
+    let input_source_map = match config.preserve_source_maps {
+        Some(false) => None,
+        Some(true) => {
+            ensure!(
+                path_exists(&input_source_map).await?,
+                "`data-wasm-opt-preserve-source-maps` is set, but there is no source map at {}",
+                input_source_map.display()
+            );
+            Some(input_source_map)
+        }
+        None => path_exists(&input_source_map)
+            .await?
+            .then_some(input_source_map),
+    };
+    let arg_input_source_map = input_source_map
+        .as_ref()
+        .map(|path| format!("--input-source-map={}", path.display()));
+    args.extend(arg_input_source_map.as_deref());
+
+    let file_name = output
+        .file_name()
+        .context("wasm-opt output has no file name")?
+        .to_string_lossy();
+    let source_map_url = config.source_map_url(&file_name).or_else(|| {
+        input_source_map
+            .as_ref()
+            .map(|_| format!("{}.map", file_name))
+    });
+    let source_map = source_map_url
+        .as_ref()
+        .map(|_| output.with_file_name(format!("{}.map", file_name)));
+    let arg_source_map = source_map
+        .as_ref()
+        .map(|path| format!("--output-source-map={}", path.display()));
+    let arg_source_map_url = source_map_url
+        .as_ref()
+        .map(|url| format!("--output-source-map-url={}", url));
+    args.extend(arg_source_map.as_deref());
+    args.extend(arg_source_map_url.as_deref());
+
+    // Invoke wasm-opt, once the pool has room for another process.
+    let permit = cfg.wasm_opt_pool.acquire().await?;
+    if cfg.wasm_opt_diagnostics.print_features {
+        print_wasm_opt_features(&wasm_opt, input).await?;
+    }
+    // Globals are only counted before and after all passes, which usually leave the number of
+    // globals unchanged apart from constant hoisting.
+    let globals_before = if config.warn_on_new_globals {
+        Some(wasm_opt_metrics(&wasm_opt, input).await?.globals())
+    } else {
+        None
+    };
+    tracing::info!("calling wasm-opt");
+    let result = common::run_command(wasm_opt_name, &wasm_opt, &args)
+        .await
+        .map_err(|err| check_target_not_found_err(err, wasm_opt_name));
+    if let Err(err) = &result {
+        // There is no output to print, so the input is printed to see what wasm-opt choked on.
+        if cfg.wasm_opt_debug_output {
+            write_wasm_opt_debug_output(cfg, &wasm_opt, input, Some(err)).await?;
+        }
+    }
+    result?;
+    if let Some(globals_before) = globals_before {
+        let globals_after = wasm_opt_metrics(&wasm_opt, output).await?.globals();
+        let added = globals_after.saturating_sub(globals_before);
+        let threshold = config
+            .new_globals_threshold
+            .unwrap_or(WasmOptConfig::DEFAULT_NEW_GLOBALS_THRESHOLD);
+        if added > threshold {
+            tracing::warn!(
+                "constant hoisting added {} globals to {}, more than the threshold of {}, which \
+                 may exceed the limits of some WASM embedders",
+                added,
+                file_name,
+                threshold
+            );
+        }
+    }
+    drop(permit);
+
+    if cfg.wasm_opt_debug_output {
+        write_wasm_opt_debug_output(cfg, &wasm_opt, output, None).await?;
+    }
+
+    // Report how much wasm-opt actually helped, compared to its input.
+    let after = fs::metadata(output)
+        .await
+        .context("error reading optimized wasm file metadata")?
+        .len();
+    report_wasm_opt_size(before, after);
+
+    // Certain pass combinations or pathological inputs make the output larger than the input, in
+    // which case the input is used instead, unless asked otherwise.
+    if after > before && !cfg.wasm_opt_always_use_output {
+        tracing::warn!(
+            "discarding the wasm-opt output as it is larger than its input, set \
+             `wasm_opt_always_use_output` to keep it"
+        );
+        fs::copy(input, output)
+            .await
+            .context("error copying the unoptimized wasm file over the wasm-opt output")?;
+        if let Some(source_map) = &source_map {
+            fs::remove_file(source_map)
+                .await
+                .context("error removing the source map of the discarded wasm-opt output")?;
+        }
+        write_extra_outputs(cfg, config, &wasm_opt, output).await?;
+        return Ok(None);
+    }
+
+    write_extra_outputs(cfg, config, &wasm_opt, output).await?;
+    Ok(source_map)
+}
+
+/// Copy the input WASM file to the output unoptimized, writing the extra outputs enabled in the
+/// config as for an optimized file. These require the wasm-opt binary, except for the size
+/// profile.
+async fn skip_wasm_opt(
+    cfg: &RtcBuild,
+    config: &WasmOptConfig,
+    wasm_opt: Option<&Path>,
+    input: &Path,
+    output: &Path,
+) -> Result<()> {
+    fs::copy(input, output)
+        .await
+        .context("error copying wasm file skipped by wasm-opt")?;
+    match wasm_opt {
+        Some(wasm_opt) => write_extra_outputs(cfg, config, wasm_opt, output).await,
+        None if cfg.wasm_size_profile => log_size_profile(output).await,
+        None => Ok(()),
+    }
+}
+
+/// Write the outputs derived from the final WASM file which are enabled in the config.
+async fn write_extra_outputs(
+    cfg: &RtcBuild,
+    config: &WasmOptConfig,
+    wasm_opt: &Path,
+    wasm: &Path,
+) -> Result<()> {
+    if config.emit_wat {
+        write_wat(wasm_opt, wasm).await?;
+    }
+    if config.emit_metrics {
+        write_wasm_opt_metrics(cfg, wasm_opt, wasm).await?;
+    }
+    if cfg.wasm_size_profile {
+        log_size_profile(wasm).await?;
+    }
+    Ok(())
+}
+
+/// Write the given WASM file in the WebAssembly text format to a `.wat` file next to it.
+async fn write_wat(wasm_opt: &Path, wasm: &Path) -> Result<()> {
+    let wat = wasm.with_extension("wat");
+    let arg_output = format!("--output={}", wat.display());
+    let target_wasm = wasm.to_string_lossy();
+    // No passes are run, so all features can be enabled for reading any binary.
+    let args = [&*target_wasm, "--all-features", "--emit-text", &arg_output];
+    common::run_command(Application::WasmOpt.name(), wasm_opt, &args).await
+}
+
+/// The metrics of a WASM file, as reported by wasm-opt.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+struct WasmOptMetrics {
+    /// The file name of the WASM file.
+    wasm: String,
+    /// The number of module elements, like `funcs` or `memory-data` bytes, and of expressions of
+    /// each type, like `Call`, by name.
+    metrics: BTreeMap<String, u64>,
+}
+
+impl WasmOptMetrics {
+    /// Parse the table printed by wasm-opt's `--metrics`, with lines like `[funcs] : 10` for the
+    /// module elements and `Block : 25` for the expressions.
+    fn parse(wasm: String, table: &str) -> Self {
+        let metrics = table
+            .lines()
+            .filter_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                let name = name.trim().trim_start_matches('[').trim_end_matches(']');
+                let value = value.split_whitespace().next()?.parse().ok()?;
+                Some((name.to_owned(), value))
+            })
+            .collect();
+        Self { wasm, metrics }
+    }
+
+    /// The number of globals of the module.
+    fn globals(&self) -> u64 {
+        self.metrics.get("globals").copied().unwrap_or_default()
+    }
+}
+
+/// Serializes writing `WASM_OPT_METRICS_FILE`, which is shared by all wasm-opt invocations.
+static WASM_OPT_METRICS_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(Default::default);
+
+/// Log the metrics wasm-opt reports for the given WASM file, and add them to the metrics file of
+/// the dist dir.
+async fn write_wasm_opt_metrics(cfg: &RtcBuild, wasm_opt: &Path, wasm: &Path) -> Result<()> {
+    let metrics = wasm_opt_metrics(wasm_opt, wasm).await?;
+    for (metric, value) in &metrics.metrics {
+        tracing::info!(wasm = %metrics.wasm, metric = %metric, value, "wasm-opt metric");
+    }
+
+    let _lock = WASM_OPT_METRICS_LOCK.lock().await;
+    let path = cfg.staging_dist.join(WASM_OPT_METRICS_FILE);
+    let mut all: Vec<serde_json::Value> = match fs::read(&path).await {
+        Ok(json) => serde_json::from_slice(&json).context("error reading wasm-opt metrics")?,
+        Err(_) => vec![],
+    };
+    all.push(serde_json::to_value(&metrics).context("error serializing wasm-opt metrics")?);
+    let json = serde_json::to_vec_pretty(&all).context("error serializing wasm-opt metrics")?;
+    fs::write(&path, json)
+        .await
+        .context("error writing wasm-opt metrics")
+}
+
+/// The metrics wasm-opt reports for the given WASM file.
+async fn wasm_opt_metrics(wasm_opt: &Path, wasm: &Path) -> Result<WasmOptMetrics> {
+    let output = Command::new(wasm_opt)
+        .arg(wasm)
+        .arg("--all-features")
+        .arg("--metrics")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .context("error spawning wasm-opt to print the metrics")?;
+    ensure!(
+        output.status.success(),
+        "error printing the metrics of {} with wasm-opt: {}",
+        wasm.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    let file_name = wasm
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let table = String::from_utf8_lossy(&output.stdout);
+    Ok(WasmOptMetrics::parse(file_name, &table))
+}
+
+/// Log the WASM proposals wasm-opt detects as used by the given WASM file, one event for each.
+async fn print_wasm_opt_features(wasm_opt: &Path, wasm: &Path) -> Result<()> {
+    let output = Command::new(wasm_opt)
+        .arg(wasm)
+        .arg("--print-features")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .context("error spawning wasm-opt to print the features")?;
+    ensure!(
+        output.status.success(),
+        "error printing the features of {} with wasm-opt: {}",
+        wasm.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    // Features are printed like the flags enabling them, like `--enable-bulk-memory`.
+    for feature in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .chain(String::from_utf8_lossy(&output.stderr).lines())
+        .filter_map(|line| line.trim().strip_prefix("--enable-"))
+    {
+        tracing::debug!(feature, wasm = ?wasm, "wasm-opt detected feature");
+    }
+    Ok(())
+}
+
+/// The file in the dist dir collecting the metrics of all wasm-opt outputs of a build.
+const WASM_OPT_METRICS_FILE: &str = "wasm-opt-metrics.json";
+
+/// The number of lines of the AST written by `write_wasm_opt_debug_output`.
+const WASM_OPT_DEBUG_LINES: usize = 100;
+
+/// Append the beginning of the AST of the given WASM file, as printed by wasm-opt, to the wasm-opt
+/// debug file of the build, along with anything wasm-opt reported on stderr, and the error of the
+/// wasm-opt run which failed to optimize it, if any. If wasm-opt fails to print the AST, the
+/// failure is recorded in the file instead of failing the build.
+async fn write_wasm_opt_debug_output(
+    cfg: &RtcBuild,
+    wasm_opt: &Path,
+    wasm: &Path,
+    failure: Option<&anyhow::Error>,
+) -> Result<()> {
+    let path = cfg.wasm_opt_debug_file().await?;
+    tracing::warn!(
+        "wasm-opt debug output is enabled and written to {:?}, this slows down builds and is \
+         meant for diagnostics only",
+        path
+    );
+    let output = Command::new(wasm_opt)
+        .arg(wasm)
+        .arg("--print-full")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await
+        .context("error spawning wasm-opt to print the AST")?;
+
+    let mut debug = format!("==> {}\n", wasm.display());
+    if let Some(failure) = failure {
+        debug.push_str(&format!(
+            "==> wasm-opt failed to optimize it: {:#}\n",
+            failure
+        ));
+    }
+    if !output.status.success() {
+        tracing::warn!(
+            "wasm-opt failed to print the AST of {:?} ({}), see {:?}",
+            wasm,
+            output.status,
+            path
+        );
+        debug.push_str(&format!("==> wasm-opt failed: {}\n", output.status));
+    }
+    for line in String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .take(WASM_OPT_DEBUG_LINES)
+    {
+        debug.push_str(line);
+        debug.push('\n');
+    }
+    if !output.stderr.is_empty() {
+        debug.push_str("==> stderr\n");
+        debug.push_str(&String::from_utf8_lossy(&output.stderr));
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await
+        .context("error opening wasm-opt debug output file")?;
+    file.write_all(debug.as_bytes())
+        .await
+        .context("error writing wasm-opt debug output")
+}
+
+/// Log the size difference between the WASM file before and after running wasm-opt.
+fn report_wasm_opt_size(before: u64, after: u64) {
+    let reduction_pct = if before == 0 {
+        0.0
+    } else {
+        (before as f64 - after as f64) / before as f64 * 100.0
+    };
+    if after > before {
+        tracing::warn!(
+            "wasm-opt increased size by {:.1}% ({} → {} bytes)",
+            -reduction_pct,
+            before,
+            after
+        );
+    } else {
+        tracing::info!(
+            "wasm-opt reduced size by {:.1}% ({} → {} bytes)",
+            reduction_pct,
+            before,
+            after
+        );
+    }
+}
+
 /// Handle invocation errors indicating that the target binary was not found, simply wrapping the
 /// error in additional context stating more clearly that the target was not found.
 fn check_target_not_found_err(err: anyhow::Error, target: &str) -> anyhow::Error {
@@ -742,3 +2620,367 @@ fn check_target_not_found_err(err: anyhow::Error, target: &str) -> anyhow::Error
         _ => err,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compatible_cli_versions_for_crate_version() {
+        let table = [
+            ("0.2.84", "0.2.83"),
+            ("0.2.85", "0.2.83"),
+            ("0.2.85", "0.2.84"),
+        ];
+        assert_eq!(
+            compatible_cli_versions(&table, "0.2.83"),
+            vec!["0.2.84", "0.2.85"]
+        );
+        assert_eq!(compatible_cli_versions(&table, "0.2.84"), vec!["0.2.85"]);
+        assert!(compatible_cli_versions(&table, "0.2.82").is_empty());
+    }
+
+    #[test]
+    fn wasm_opt_nontrapping_float_to_int() -> Result<()> {
+        let enabled = |attrs: &[(&str, &str)], release| -> Result<bool> {
+            let attrs = attrs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect();
+            let profile =
+                WasmOptConfig::from_cargo_profile(if release { "release" } else { "dev" });
+            let args = WasmOptConfig::from_attrs(&attrs, release, profile, true)?.args();
+            Ok(args.contains(&"--enable-nontrapping-float-to-int".to_owned()))
+        };
+        assert!(!enabled(&[], true)?);
+        assert!(!enabled(&[("data-wasm-opt-preset", "size")], true)?);
+        assert!(enabled(&[("data-wasm-opt-preset", "release")], true)?);
+        assert!(enabled(
+            &[("data-wasm-opt-enable-nontrapping-float-to-int", "")],
+            false
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    fn cargo_profile_inherits_release() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let manifest = dir.path().join("Cargo.toml");
+        std::fs::write(
+            &manifest,
+            r#"
+            [profile.production]
+            inherits = "release"
+
+            [profile.production-small]
+            inherits = "production"
+
+            [profile.ci]
+            inherits = "dev"
+            "#,
+        )?;
+        assert!(profile_inherits_release("production", &manifest));
+        assert!(profile_inherits_release("production-small", &manifest));
+        assert!(!profile_inherits_release("ci", &manifest));
+        assert!(!profile_inherits_release("unknown", &manifest));
+        assert!(!profile_inherits_release(
+            "production",
+            &dir.path().join("missing.toml")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn wasm_opt_level_from_cargo_profile() {
+        let release = WasmOptConfig::from_cargo_profile("release");
+        assert!(release.is_enabled());
+        assert!(release.args().contains(&"--strip-debug".to_owned()));
+        let dev = WasmOptConfig::from_cargo_profile("dev");
+        assert_eq!(dev.args(), vec!["-O1", "--debuginfo"]);
+        assert!(!WasmOptConfig::from_cargo_profile("custom").is_enabled());
+    }
+
+    #[test]
+    fn wasm_opt_config_for_profile() -> Result<()> {
+        let profiles: HashMap<String, WasmOptConfig> = toml::from_str(
+            r#"
+            [staging]
+            level = "s"
+            preserve_debug_info = true
+            "#,
+        )?;
+        let staging = WasmOptConfig::for_profile("staging", &profiles, false);
+        assert_eq!(staging.args(), vec!["-Os", "--name-types", "--debuginfo"]);
+        assert!(WasmOptConfig::for_profile("release", &profiles, false)
+            .args()
+            .contains(&"--strip-debug".to_owned()));
+        assert!(!WasmOptConfig::for_profile("production", &profiles, false).is_enabled());
+        // Unconfigured custom profiles fall back to the release config in release mode.
+        assert!(WasmOptConfig::for_profile("production", &profiles, true)
+            .args()
+            .contains(&"--strip-debug".to_owned()));
+        assert!(!WasmOptConfig::for_profile("dev", &profiles, true)
+            .args()
+            .contains(&"--strip-debug".to_owned()));
+
+        let attrs = [("data-wasm-opt".to_owned(), "z".to_owned())].into();
+        let config = WasmOptConfig::from_attrs(&attrs, true, staging.clone(), true)?;
+        assert_eq!(config.args(), vec!["-Oz", "--name-types", "--debuginfo"]);
+        let attrs = [("data-wasm-opt-name-types".to_owned(), "false".to_owned())].into();
+        let config = WasmOptConfig::from_attrs(&attrs, true, staging, true)?;
+        assert_eq!(config.args(), vec!["-Os", "--debuginfo"]);
+
+        assert!(toml::from_str::<WasmOptConfig>("level = \"5\"").is_err());
+        assert!(toml::from_str::<WasmOptConfig>("unknown = true").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn wasm_opt_dce() -> Result<()> {
+        let args = |attrs: &[&str]| -> Result<Vec<String>> {
+            let attrs = attrs
+                .iter()
+                .map(|name| (name.to_string(), String::new()))
+                .collect();
+            let profile = WasmOptConfig::from_cargo_profile("release");
+            Ok(WasmOptConfig::from_attrs(&attrs, true, profile, true)?.args())
+        };
+        // Dead code is eliminated after the optimization passes of the level.
+        assert_eq!(
+            args(&["data-wasm-opt-dce", "data-wasm-opt-asyncify"])?[..3],
+            ["--asyncify", "-O", "--dce"]
+        );
+        assert!(!args(&[])?.contains(&"--dce".to_owned()));
+        assert_eq!(
+            args(&["data-wasm-opt-dce", "data-wasm-opt-const-hoisting"])?[..3],
+            ["-O", "--dce", "--const-hoisting"]
+        );
+        assert!(args(&["data-wasm-opt-warn-on-new-globals"]).is_err());
+
+        let mut attrs = Attrs::new();
+        attrs.insert("data-wasm-opt-preset".into(), "release".into());
+        let profile = WasmOptConfig::from_cargo_profile("release");
+        let release = WasmOptConfig::from_attrs(&attrs, true, profile, true)?;
+        assert_eq!(release.args()[..2], ["-O", "--dce"]);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_wasm_opt_metrics() {
+        let table = "total\n [funcs]        : 12      \n [memory-data]  : 1024    \n Block          : 30      \n";
+        let metrics = WasmOptMetrics::parse("app_bg.wasm".into(), table);
+        assert_eq!(
+            metrics.metrics,
+            [("funcs", 12), ("memory-data", 1024), ("Block", 30)]
+                .iter()
+                .map(|(name, value)| (name.to_string(), *value))
+                .collect()
+        );
+        assert_eq!(metrics.globals(), 0);
+    }
+
+    #[test]
+    fn wasm_opt_multivalue() {
+        let config = WasmOptConfig {
+            level: WasmOptLevel::Two,
+            strip_debug: true,
+            features: WasmFeatures {
+                multivalue: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            config.args(),
+            vec![
+                "-O2",
+                "--strip-debug",
+                "--generate-stack-ir",
+                "--optimize-stack-ir",
+                "--enable-multivalue"
+            ]
+        );
+    }
+
+    #[test]
+    fn wasm_opt_closed_world() -> Result<()> {
+        let closed_world = |attrs: &[&str], wasm_bindgen| -> Result<bool> {
+            let attrs = attrs
+                .iter()
+                .map(|name| (name.to_string(), String::new()))
+                .collect();
+            let profile = WasmOptConfig::from_cargo_profile("release");
+            Ok(WasmOptConfig::from_attrs(&attrs, true, profile, wasm_bindgen)?.closed_world)
+        };
+        assert!(closed_world(&[], true)?);
+        assert!(!closed_world(&["data-wasm-opt-open-world"], true)?);
+        assert!(!closed_world(&[], false)?);
+        assert!(closed_world(&["data-wasm-opt-closed-world"], false)?);
+        assert_eq!(binaryen_version_number("version_112"), Some(112));
+        assert_eq!(binaryen_version_number("latest"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn wasm_opt_gc_requires_reference_types() -> Result<()> {
+        let features = WasmFeatures {
+            gc: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            features.args(),
+            vec!["--enable-reference-types", "--enable-gc"]
+        );
+        assert!(WasmFeatures::default().args().is_empty());
+
+        assert!(features.check_version("version_110").is_err());
+        features.check_version("version_113")?;
+        features.check_version("version_116-dirty")?;
+        WasmFeatures::default().check_version("version_110")?;
+        Ok(())
+    }
+
+    #[test]
+    fn wasm_opt_strings_enable_gc() -> Result<()> {
+        let attrs = [("data-wasm-opt-enable-strings".to_owned(), String::new())].into();
+        let profile = WasmOptConfig::from_cargo_profile("release");
+        let config = WasmOptConfig::from_attrs(&attrs, true, profile, true)?;
+        assert_eq!(
+            config.features.args(),
+            vec![
+                "--enable-reference-types",
+                "--enable-gc",
+                "--enable-strings"
+            ]
+        );
+        assert!(config.features.check_version("version_112").is_err());
+        config.features.check_version("version_113")?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn package_json_from_manifest() -> Result<()> {
+        let manifest = CargoMetadata::new(Path::new("tests/data/package-json/Cargo.toml")).await?;
+        let json = package_json(&manifest.package, "app-1a2b.js", Some("app-1a2b.d.ts"));
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "package-json-fixture",
+                "version": "1.2.3",
+                "main": "app-1a2b.js",
+                "module": "app-1a2b.js",
+                "types": "app-1a2b.d.ts",
+            })
+        );
+
+        let json = package_json(&manifest.package, "app-1a2b.js", None);
+        assert!(json.get("types").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn wasm_opt_pass_args() -> Result<()> {
+        let args = parse_wasm_opt_pass_args(
+            " inline-max-function-size@100  asyncify-imports@env.sleep,env.fetch ",
+        )?;
+        assert_eq!(
+            args,
+            vec![
+                ("inline-max-function-size".to_owned(), "100".to_owned()),
+                (
+                    "asyncify-imports".to_owned(),
+                    "env.sleep,env.fetch".to_owned()
+                ),
+            ]
+        );
+        assert!(parse_wasm_opt_pass_args("inline-max-function-size").is_err());
+        assert!(parse_wasm_opt_pass_args("@100").is_err());
+        assert!(parse_wasm_opt_pass_args("$(rm)@100").is_err());
+        Ok(())
+    }
+
+    /// The runtime config of a build of an empty `index.html` in the given dir, configured by
+    /// the given `Trunk.toml`.
+    fn test_rtc_build(dir: &Path, trunk_toml: &str) -> Result<Arc<RtcBuild>> {
+        std::fs::write(dir.join("index.html"), "<html></html>")?;
+        let config = dir.join("Trunk.toml");
+        std::fs::write(
+            &config,
+            format!("[build]\ntarget = \"index.html\"\n{}", trunk_toml),
+        )?;
+        crate::config::ConfigOpts::rtc_build(Default::default(), Some(config))
+    }
+
+    #[test]
+    fn bindgen_target_of_multi_target_build() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cfg = test_rtc_build(
+            dir.path(),
+            "targets = [\"wasm32-unknown-unknown\", \"wasm32-wasip2\"]",
+        )?;
+        let web = cfg.for_target("wasm32-unknown-unknown")?;
+        let wasi = cfg.for_target(WASI_P2_CARGO_TARGET)?;
+        let main = RustAppType::Main;
+
+        // The cargo target decides, regardless of the attr.
+        for attr in [None, Some(BindgenTarget::WasiP2)] {
+            assert_eq!(resolve_bindgen_target(&web, attr, main), BindgenTarget::Web);
+            assert_eq!(
+                resolve_bindgen_target(&wasi, attr, main),
+                BindgenTarget::WasiP2
+            );
+        }
+        assert_eq!(
+            resolve_bindgen_target(&wasi, Some(BindgenTarget::Bundler), main),
+            BindgenTarget::WasiP2
+        );
+        assert_eq!(
+            resolve_bindgen_target(&web, Some(BindgenTarget::Bundler), main),
+            BindgenTarget::Bundler
+        );
+
+        // A single-target build is switched to WASI by the attr.
+        assert_eq!(
+            resolve_bindgen_target(&cfg, Some(BindgenTarget::WasiP2), main),
+            BindgenTarget::WasiP2
+        );
+        assert_eq!(resolve_bindgen_target(&cfg, None, main), BindgenTarget::Web);
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn wasm_opt_skipped_by_min_size_emits_wat() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir()?;
+        let cfg = test_rtc_build(dir.path(), "")?;
+        let attrs = [
+            ("data-wasm-opt-min-size".to_owned(), "1000".to_owned()),
+            ("data-wasm-opt-emit-wat".to_owned(), String::new()),
+        ]
+        .into();
+        let profile = WasmOptConfig::from_cargo_profile("release");
+        let config = WasmOptConfig::from_attrs(&attrs, true, profile, true)?;
+
+        // A stand-in for wasm-opt, writing a module to the file passed with `--output`.
+        let wasm_opt = dir.path().join("wasm-opt");
+        std::fs::write(
+            &wasm_opt,
+            "#!/bin/sh\nfor arg; do case \"$arg\" in --output=*) echo '(module)' > \
+             \"${arg#--output=}\";; esac; done\n",
+        )?;
+        std::fs::set_permissions(&wasm_opt, std::fs::Permissions::from_mode(0o755))?;
+
+        let input = dir.path().join("input.wasm");
+        let output = dir.path().join("output.wasm");
+        std::fs::write(&input, b"\0asm\x01\0\0\0")?;
+        skip_wasm_opt(&cfg, &config, Some(&wasm_opt), &input, &output).await?;
+        assert_eq!(std::fs::read(&output)?, std::fs::read(&input)?);
+        assert_eq!(
+            std::fs::read_to_string(output.with_extension("wat"))?,
+            "(module)\n"
+        );
+        Ok(())
+    }
+}
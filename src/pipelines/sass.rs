@@ -1,18 +1,59 @@
 //! Sass/Scss asset pipeline.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use nipper::Document;
 use tokio::fs;
+use tokio::process::Command;
 use tokio::task::JoinHandle;
 
+use super::postcss::run_postcss;
+use super::sass_cache::SassCache;
+use super::sass_overlay::error_overlay_css;
 use super::{AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_HREF, ATTR_INLINE};
-use crate::common;
-use crate::config::RtcBuild;
+use crate::common::path_exists;
+use crate::config::{RtcBuild, SassCharset, SassInputSyntax, SASS_WATCH_DIR};
 use crate::tools::{self, Application};
 
+/// The deprecations of sass which can be silenced with `--silence-deprecation`, along with the
+/// first sass version which knows them. The flag itself was added in sass 1.74.0.
+pub const SASS_DEPRECATIONS: &[(&str, &str)] = &[
+    ("abs-percent", "1.74.0"),
+    ("bogus-combinators", "1.74.0"),
+    ("call-string", "1.74.0"),
+    ("color-4-api", "1.79.0"),
+    ("color-functions", "1.79.0"),
+    ("color-module-compat", "1.74.0"),
+    ("css-function-mixin", "1.76.0"),
+    ("duplicate-var-flags", "1.74.0"),
+    ("elseif", "1.74.0"),
+    ("feature-exists", "1.78.0"),
+    ("fs-importer-cwd", "1.74.0"),
+    ("function-units", "1.74.0"),
+    ("global-builtin", "1.80.0"),
+    ("import", "1.80.0"),
+    ("mixed-decls", "1.77.7"),
+    ("moz-document", "1.74.0"),
+    ("new-global", "1.74.0"),
+    ("null-alpha", "1.74.0"),
+    ("relative-canonical", "1.74.0"),
+    ("slash-div", "1.74.0"),
+    ("strict-unary", "1.74.0"),
+];
+
+/// Whether sass compiles the stylesheets on every build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SassIncrementalMode {
+    /// Compile the stylesheet on every build.
+    Always,
+    /// Compile the stylesheet into a cache dir kept between builds with a `sass --watch` process,
+    /// which keeps running and recompiles the stylesheet as soon as it or its dependencies change.
+    Watch,
+}
+
 /// A sass/scss asset pipeline.
 pub struct Sass {
     /// The ID of this pipeline's source HTML element.
@@ -57,6 +98,130 @@ impl Sass {
         tokio::spawn(self.run())
     }
 
+    /// The syntax sass parses the stylesheet with by its file extension.
+    fn extension_syntax(&self) -> SassInputSyntax {
+        match self.asset.ext.as_deref() {
+            Some(Self::TYPE_SASS) => SassInputSyntax::Sass,
+            Some("css") => SassInputSyntax::Css,
+            _ => SassInputSyntax::Scss,
+        }
+    }
+
+    /// The arguments for having sass parse the stylesheet with the configured syntax, if it
+    /// differs from the one of its file extension.
+    ///
+    /// Sass picks the syntax of files by their extension, so the stylesheet is passed through stdin
+    /// instead, for which the syntax can be set.
+    fn stdin_syntax_args(&self) -> Result<Option<[&'static str; 2]>> {
+        let syntax = self.cfg.sass_input_syntax;
+        if syntax == SassInputSyntax::Auto || syntax == self.extension_syntax() {
+            return Ok(None);
+        }
+        let arg_indented = match syntax {
+            SassInputSyntax::Scss => "--no-indented",
+            SassInputSyntax::Sass => "--indented",
+            _ => bail!(
+                "sass only parses stylesheets with the `.css` extension as plain CSS, which {} \
+                 does not have",
+                self.asset.path.display()
+            ),
+        };
+        Ok(Some(["--stdin", arg_indented]))
+    }
+
+    /// Compile the stylesheet read from stdin with the given arguments.
+    async fn compile_stdin(&self, sass: &Path, args: &[&str]) -> Result<()> {
+        let input = fs::File::open(&self.asset.path)
+            .await
+            .with_context(|| format!("error opening {}", self.asset.path.display()))?
+            .into_std()
+            .await;
+        run_sass(sass, args, Some(input)).await
+    }
+
+    /// Compile the stylesheet with the given arguments, returning the CSS and whether the
+    /// compilation succeeded.
+    ///
+    /// With `sass_error_overlay`, or with `--error-css`, a failed compilation yields a stylesheet
+    /// showing the error in the browser, which is returned instead of failing the build.
+    async fn compile(
+        &self,
+        sass: &Path,
+        args: &[&str],
+        path_str: &str,
+        file_path: &str,
+    ) -> Result<(String, bool)> {
+        let mut args = args.to_vec();
+        let stdin = self.stdin_syntax_args()?.is_some();
+        // Only files can be watched, the stylesheet read from stdin is compiled on every build.
+        let incremental = if stdin {
+            SassIncrementalMode::Always
+        } else {
+            self.cfg.sass_incremental
+        };
+        let (result, output) = match incremental {
+            SassIncrementalMode::Always if stdin => {
+                args.push(file_path);
+                let result = self.compile_stdin(sass, &args).await;
+                (result, PathBuf::from(file_path))
+            }
+            SassIncrementalMode::Always => {
+                args.push(path_str);
+                args.push(file_path);
+                let result = run_sass(sass, &args, None).await;
+                (result, PathBuf::from(file_path))
+            }
+            SassIncrementalMode::Watch => {
+                // The cached output is specific to the stylesheet and the arguments compiling it,
+                // so changing either never picks up an outdated output.
+                let cache_dir = tools::cache_dir().await?.join(SASS_WATCH_DIR);
+                fs::create_dir_all(&cache_dir)
+                    .await
+                    .context("error creating sass cache dir")?;
+                let key = seahash::hash(format!("{} {:?}", path_str, args).as_bytes());
+                let cache_path = cache_dir.join(format!(
+                    "{}-{:x}.css",
+                    &self.asset.file_stem.to_string_lossy(),
+                    key
+                ));
+                let arg_watch =
+                    format!("{}:{}", path_str, dunce::simplified(&cache_path).display());
+                args.push("--watch");
+                args.push(&arg_watch);
+                let result = self
+                    .cfg
+                    .sass_daemons
+                    .compile(sass, &args, &cache_path)
+                    .await;
+                (result, cache_path)
+            }
+        };
+
+        let (overlay, succeeded) = match result {
+            Ok(()) => (None, true),
+            Err(err) if self.cfg.sass_error_overlay => {
+                tracing::error!("{:#}", err);
+                (Some(error_overlay_css(&format!("{:#}", err))), false)
+            }
+            Err(err) if self.cfg.sass_error_css && path_exists(&output).await? => {
+                tracing::error!("{:#}", err);
+                (None, false)
+            }
+            Err(err) => return Err(err),
+        };
+        let css = match overlay {
+            Some(css) => css,
+            None => fs::read_to_string(&output)
+                .await
+                .context("error reading sass output")?,
+        };
+        // The output dir of the watch mode is kept between builds.
+        if incremental == SassIncrementalMode::Always && path_exists(&output).await? {
+            fs::remove_file(&output).await?;
+        }
+        Ok((css, succeeded))
+    }
+
     /// Run this pipeline.
     #[tracing::instrument(level = "trace", skip(self))]
     async fn run(self) -> Result<TrunkAssetPipelineOutput> {
@@ -71,18 +236,126 @@ impl Sass {
             "expanded"
         };
         let path_str = dunce::simplified(&self.asset.path).display().to_string();
-        let file_name = format!("{}.css", &self.asset.file_stem.to_string_lossy());
+        // Mirror the location of the stylesheet relative to the source HTML file in the dist dir,
+        // like `styles/pages/home.css`. Stylesheets outside of its dir are placed at the root.
+        let out_dir = self
+            .cfg
+            .sass_mirror_structure
+            .then(|| self.asset.path.parent())
+            .flatten()
+            .and_then(|dir| dir.strip_prefix(&self.cfg.target_parent).ok())
+            .map(Path::to_owned)
+            .unwrap_or_default();
+        fs::create_dir_all(self.cfg.staging_dist.join(&out_dir))
+            .await
+            .context("error creating sass output dir")?;
+        let out_prefix: String = out_dir
+            .components()
+            .map(|dir| format!("{}/", dir.as_os_str().to_string_lossy()))
+            .collect();
+        let file_name = format!(
+            "{}{}.css",
+            out_prefix,
+            &self.asset.file_stem.to_string_lossy()
+        );
         let file_path = dunce::simplified(&self.cfg.staging_dist.join(&file_name))
             .display()
             .to_string();
-        let args = &["--no-source-map", "-s", style, &path_str, &file_path];
+        let mut args = vec!["--no-source-map", "-s", style];
+        if self.cfg.sass_quiet_deps {
+            args.push("--quiet-deps");
+        }
+        if self.cfg.sass_verbose {
+            args.push("--verbose");
+        }
+        if self.cfg.sass_no_unicode {
+            args.push("--no-unicode");
+        }
+        // Sass emits error stylesheets by default when writing to a file.
+        args.push(if self.cfg.sass_error_css {
+            "--error-css"
+        } else {
+            "--no-error-css"
+        });
+        match self.cfg.sass_charset {
+            SassCharset::Auto => {}
+            SassCharset::Always => args.push("--charset"),
+            SassCharset::Never => args.push("--no-charset"),
+        }
+        // Imports relative to the stylesheet read from stdin are resolved by its dir as load path.
+        let stdin_syntax_args = self.stdin_syntax_args()?;
+        let arg_stdin_load_path = stdin_syntax_args
+            .and(self.asset.path.parent())
+            .map(|dir| format!("--load-path={}", dunce::simplified(dir).display()));
+        if let Some(stdin_syntax_args) = stdin_syntax_args {
+            args.extend(stdin_syntax_args);
+        }
+        args.extend(arg_stdin_load_path.as_deref());
+        for deprecation in &self.cfg.sass_silence_deprecations {
+            args.push("--silence-deprecation");
+            args.push(deprecation);
+        }
+        // Sass tries the load paths in order, so the custom import dirs take precedence over the
+        // packages in `node_modules`.
+        let arg_import_paths: Vec<_> = self
+            .cfg
+            .sass_import_paths
+            .iter()
+            .map(|dir| format!("--load-path={}", dunce::simplified(dir).display()))
+            .collect();
+        args.extend(arg_import_paths.iter().map(String::as_str));
+        // Sass' node package importer resolves `pkg:` URLs from the `node_modules` dirs above the
+        // entrypoint. Adding the configured dir as load path covers projects keeping it elsewhere,
+        // as well as plain package imports like `@import "bootstrap/scss/bootstrap"`.
+        let arg_load_path = self
+            .cfg
+            .sass_pkg_importer
+            .as_ref()
+            .map(|dir| format!("--load-path={}", dunce::simplified(dir).display()));
+        if let Some(arg_load_path) = &arg_load_path {
+            if self.cfg.sass_node_pkg_importer {
+                args.push("--pkg-importer=node");
+            }
+            args.push(arg_load_path);
+        }
 
         let rel_path = crate::common::strip_prefix(&self.asset.path);
-        tracing::info!(path = ?rel_path, "compiling sass/scss");
-        common::run_command(Application::Sass.name(), &sass, args).await?;
-
-        let css = fs::read_to_string(&file_path).await?;
-        fs::remove_file(&file_path).await?;
+        let cache = SassCache::new().await?;
+        let load_paths: Vec<_> = self
+            .cfg
+            .sass_import_paths
+            .iter()
+            .chain(&self.cfg.sass_pkg_importer)
+            .cloned()
+            .collect();
+        // The output depends on the sass version as well, which is part of the path of its binary.
+        let sass_str = sass.to_string_lossy();
+        let mut key_args = args.clone();
+        key_args.push(&sass_str);
+        let cache_key = SassCache::key(&self.asset.path, &load_paths, &key_args).await?;
+        let cached = match &cache_key {
+            Some(key) => cache.get(key).await?,
+            None => None,
+        };
+        let sass_unchanged = cached.is_some();
+        let css = if let Some(css) = cached {
+            tracing::info!(path = ?rel_path, "using cached sass/scss output");
+            css
+        } else {
+            tracing::info!(path = ?rel_path, "compiling sass/scss");
+            let (css, succeeded) = self.compile(&sass, &args, &path_str, &file_path).await?;
+            if let Some(key) = cache_key.as_ref().filter(|_| succeeded) {
+                cache.put(key, &css).await?;
+            }
+            css
+        };
+        // The cache holds the output of sass, as PostCSS depends on the browserslist as well.
+        let css = if self.cfg.postcss {
+            tracing::info!(path = ?rel_path, "running postcss");
+            run_postcss(&self.cfg, css, &self.asset.path).await?
+        } else {
+            css
+        };
 
         // Check if the specified SASS/SCSS file should be inlined.
         let css_ref = if self.use_inline {
@@ -95,7 +368,14 @@ impl Sass {
             let file_name = self
                 .cfg
                 .filehash
-                .then(|| format!("{}-{:x}.css", &self.asset.file_stem.to_string_lossy(), hash))
+                .then(|| {
+                    format!(
+                        "{}{}-{:x}.css",
+                        out_prefix,
+                        &self.asset.file_stem.to_string_lossy(),
+                        hash
+                    )
+                })
                 .unwrap_or(file_name);
             let file_path = self.cfg.staging_dist.join(&file_name);
 
@@ -113,10 +393,35 @@ impl Sass {
             cfg: self.cfg.clone(),
             id: self.id,
             css_ref,
+            skipped: if sass_unchanged { vec!["sass"] } else { vec![] },
         }))
     }
 }
 
+/// Run sass with the given arguments, reading the stylesheet from `stdin` if given.
+///
+/// The messages of sass are captured, so a failed compilation reports its error, which
+/// `sass_error_overlay` shows in the browser.
+async fn run_sass(sass: &Path, args: &[&str], stdin: Option<std::fs::File>) -> Result<()> {
+    tracing::debug!(?args, "sass args");
+    let mut command = Command::new(sass);
+    command
+        .args(args)
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::piped());
+    if let Some(stdin) = stdin {
+        command.stdin(stdin);
+    }
+    let output = command.output().await.context("error spawning sass call")?;
+    let messages = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        bail!("sass call returned a bad status:\n{}", messages.trim_end());
+    }
+    // Warnings, like deprecations, are passed on as sass printed them.
+    eprint!("{}", messages);
+    Ok(())
+}
+
 /// The output of a sass/scss build pipeline.
 pub struct SassOutput {
     /// The runtime build config.
@@ -125,6 +430,8 @@ pub struct SassOutput {
     pub id: usize,
     /// Data on the finalized output file.
     pub css_ref: CssRef,
+    /// The steps skipped as their input was unchanged since the previous build.
+    pub skipped: Vec<&'static str>,
 }
 
 /// The resulting CSS of the SASS/SCSS compilation.
@@ -0,0 +1,277 @@
+//! Cache of sass compilation results, shared by all projects.
+//!
+//! Results are keyed by the SHA-256 of the compiler arguments and the contents of the stylesheet
+//! and all stylesheets it imports, so any change to them misses the cache.
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+
+use crate::config::SASS_COMPILE_CACHE_DIR;
+use crate::tools::cache_dir;
+
+/// A cache entry, mapping the hash of the inputs to the compiled output.
+#[derive(Deserialize, Serialize)]
+struct SassCacheEntry {
+    /// The SHA-256 of the compiled CSS.
+    output_hash: String,
+    /// The file holding the compiled CSS, relative to the cache dir.
+    output_path: PathBuf,
+}
+
+/// Content addressed cache of sass compilation results.
+pub(super) struct SassCache {
+    dir: PathBuf,
+}
+
+impl SassCache {
+    /// Open the cache in `{cache_dir}/sass-cache`.
+    pub(super) async fn new() -> Result<Self> {
+        let dir = cache_dir().await?.join(SASS_COMPILE_CACHE_DIR);
+        fs::create_dir_all(&dir)
+            .await
+            .context("error creating sass cache dir")?;
+        Ok(Self { dir })
+    }
+
+    /// Compute the cache key of compiling the given stylesheet with the given arguments.
+    ///
+    /// Imports are resolved relative to the stylesheet and in the given load paths. Returns
+    /// `None` if not all dependencies can be determined, like for imports of `pkg:` URLs, in
+    /// which case the result must not be cached.
+    pub(super) async fn key(
+        stylesheet: &Path,
+        load_paths: &[PathBuf],
+        args: &[&str],
+    ) -> Result<Option<String>> {
+        let mut hasher = Sha256::new();
+        for arg in args {
+            hasher.update(arg.as_bytes());
+            hasher.update([0]);
+        }
+
+        let mut pending = vec![stylesheet.to_owned()];
+        let mut visited = HashSet::new();
+        while let Some(path) = pending.pop() {
+            if !visited.insert(path.clone()) {
+                continue;
+            }
+            let content = fs::read_to_string(&path)
+                .await
+                .with_context(|| format!("error reading {}", path.display()))?;
+            hasher.update(path.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update(content.as_bytes());
+            hasher.update([0]);
+
+            // Stylesheets loaded at runtime are not known up front.
+            if content.contains("load-css") {
+                return Ok(None);
+            }
+            let indented = matches!(path.extension(), Some(ext) if ext == "sass");
+            let dir = path.parent().unwrap_or_else(|| Path::new(""));
+            for url in imports(&content, indented) {
+                match resolve(&url, dir, load_paths) {
+                    Some(import) => pending.push(import),
+                    None => return Ok(None),
+                }
+            }
+        }
+
+        Ok(Some(format!("{:x}", hasher.finalize())))
+    }
+
+    /// Get the cached output for the given key, if any.
+    pub(super) async fn get(&self, key: &str) -> Result<Option<String>> {
+        let entry = match fs::read(self.entry_path(key)).await {
+            Ok(entry) => entry,
+            Err(_) => return Ok(None),
+        };
+        let output = match serde_json::from_slice::<SassCacheEntry>(&entry) {
+            Ok(entry) => fs::read_to_string(self.dir.join(&entry.output_path))
+                .await
+                .ok()
+                .filter(|css| sha256(css) == entry.output_hash),
+            Err(_) => None,
+        };
+        // Entries whose output is missing or was modified are of no further use.
+        if output.is_none() {
+            tracing::debug!(key, "invalidating corrupted sass cache entry");
+            self.invalidate(key).await?;
+        }
+        Ok(output)
+    }
+
+    /// Store the output for the given key.
+    pub(super) async fn put(&self, key: &str, css: &str) -> Result<()> {
+        let output_hash = sha256(css);
+        let output_path = PathBuf::from(format!("{}.css", output_hash));
+        fs::write(self.dir.join(&output_path), css)
+            .await
+            .context("error writing sass cache output")?;
+        // The entry is written last, so it never refers to a partially written output.
+        let entry = serde_json::to_vec(&SassCacheEntry {
+            output_hash,
+            output_path,
+        })
+        .context("error serializing sass cache entry")?;
+        fs::write(self.entry_path(key), entry)
+            .await
+            .context("error writing sass cache entry")?;
+        Ok(())
+    }
+
+    /// Remove the entry for the given key. Its output may still be referred to by other entries,
+    /// and is kept.
+    pub(super) async fn invalidate(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.entry_path(key)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(err).context("error removing sass cache entry")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+}
+
+/// The hex encoded SHA-256 of the given CSS.
+fn sha256(css: &str) -> String {
+    format!("{:x}", Sha256::digest(css.as_bytes()))
+}
+
+/// The URLs imported by a stylesheet with `@use`, `@forward` or `@import`, apart from built-in
+/// modules and plain CSS imports.
+///
+/// Commented out imports are included as well, which at worst adds unneeded dependencies.
+fn imports(content: &str, indented: bool) -> Vec<String> {
+    // Statements of the indented syntax end at the end of the line.
+    let terminator = if indented { '\n' } else { ';' };
+    let mut urls = vec![];
+    for (idx, _) in content.match_indices('@') {
+        let rest = &content[idx + 1..];
+        let (rule, rest) = match rest.split_once(|c: char| !c.is_ascii_alphabetic()) {
+            Some((rule, _)) => (rule, &rest[rule.len()..]),
+            None => continue,
+        };
+        if !matches!(rule, "use" | "forward" | "import") {
+            continue;
+        }
+        let statement = rest.split(terminator).next().unwrap_or_default();
+        let mut strings = quoted_strings(statement);
+        // Only `@import` takes several URLs, the others are followed by their configuration.
+        if rule != "import" {
+            strings.truncate(1);
+        }
+        urls.extend(strings.into_iter().filter(|url| {
+            !url.starts_with("sass:")
+                && !(rule == "import"
+                    && (url.ends_with(".css")
+                        || url.starts_with("http://")
+                        || url.starts_with("https://")
+                        || url.starts_with("//")))
+        }));
+    }
+    urls
+}
+
+/// The strings quoted with `"` or `'` in the given text.
+fn quoted_strings(text: &str) -> Vec<String> {
+    let mut strings = vec![];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '"' || c == '\'' {
+            strings.push(chars.by_ref().take_while(|&end| end != c).collect());
+        }
+    }
+    strings
+}
+
+/// Resolve an imported URL to a file, following sass' rules for partials, extensions and index
+/// files.
+fn resolve(url: &str, dir: &Path, load_paths: &[PathBuf]) -> Option<PathBuf> {
+    if url.contains(':') {
+        // Custom importers like `pkg:` are not resolved here.
+        return None;
+    }
+    let url = Path::new(url);
+    let name = url.file_name()?.to_string_lossy();
+    let has_extension = matches!(
+        url.extension().and_then(|ext| ext.to_str()),
+        Some("scss" | "sass" | "css")
+    );
+
+    std::iter::once(dir)
+        .chain(load_paths.iter().map(PathBuf::as_path))
+        .find_map(|base| {
+            let path = base.join(url);
+            let parent = path.parent()?;
+            let candidates = if has_extension {
+                vec![path.clone(), parent.join(format!("_{}", name))]
+            } else {
+                let mut candidates = vec![];
+                for ext in ["scss", "sass", "css"] {
+                    candidates.push(parent.join(format!("{}.{}", name, ext)));
+                    candidates.push(parent.join(format!("_{}.{}", name, ext)));
+                }
+                for ext in ["scss", "sass", "css"] {
+                    candidates.push(path.join(format!("_index.{}", ext)));
+                    candidates.push(path.join(format!("index.{}", ext)));
+                }
+                candidates
+            };
+            candidates.into_iter().find(|candidate| candidate.is_file())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_imports() {
+        let scss = r#"
+            @use "sass:math";
+            @use 'base' with ($font: "Arial");
+            @forward "src/list" hide list-reset;
+            @import "a", "theme.css", "https://fonts.example/font";
+            @import
+                "b";
+            .a { width: math.div(1, 2); }
+        "#;
+        assert_eq!(imports(scss, false), vec!["base", "src/list", "a", "b"]);
+
+        let sass = "@use \"base\"\n.a\n  color: red\n";
+        assert_eq!(imports(sass, true), vec!["base"]);
+    }
+
+    #[tokio::test]
+    async fn key_covers_dependencies() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let main = dir.path().join("main.scss");
+        fs::write(&main, "@use 'colors';\n.a { color: colors.$primary; }").await?;
+        fs::create_dir(dir.path().join("colors")).await?;
+        let colors = dir.path().join("colors").join("_index.scss");
+        fs::write(&colors, "$primary: red;").await?;
+
+        let key = SassCache::key(&main, &[], &["-s", "expanded"]).await?;
+        assert!(key.is_some());
+        assert_eq!(key, SassCache::key(&main, &[], &["-s", "expanded"]).await?);
+        assert_ne!(
+            key,
+            SassCache::key(&main, &[], &["-s", "compressed"]).await?
+        );
+
+        fs::write(&colors, "$primary: blue;").await?;
+        assert_ne!(key, SassCache::key(&main, &[], &["-s", "expanded"]).await?);
+
+        fs::write(&main, "@use 'pkg:bootstrap';").await?;
+        assert_eq!(SassCache::key(&main, &[], &[]).await?, None);
+        Ok(())
+    }
+}
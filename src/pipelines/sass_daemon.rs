@@ -0,0 +1,178 @@
+//! Long running sass processes for watch builds.
+//!
+//! Starting sass takes a noticeable amount of time compared to compiling a typical stylesheet.
+//! During watch builds, every stylesheet is therefore compiled by a `sass --watch` process, kept
+//! running between builds, which recompiles it as soon as it or any of its dependencies change.
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use tokio::fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
+
+/// The time to wait before restarting a crashed sass process.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+
+/// The outcome of the latest compilation of a sass process, `None` until the first one finished.
+type SassStatus = Option<Result<(), String>>;
+
+/// The sass processes of a watch build, one for each stylesheet and set of arguments.
+#[derive(Clone, Debug)]
+pub struct SassDaemons {
+    /// The running processes, by the path of their output.
+    daemons: Arc<Mutex<HashMap<PathBuf, SassDaemon>>>,
+    /// Sent on whenever a process finished compiling, to trigger a new build.
+    rebuild_tx: mpsc::Sender<()>,
+    /// The receiving end of `rebuild_tx`, until taken by the watch system.
+    rebuild_rx: Arc<std::sync::Mutex<Option<mpsc::Receiver<()>>>>,
+}
+
+impl SassDaemons {
+    pub fn new() -> Self {
+        // A single pending rebuild covers any number of compilations.
+        let (rebuild_tx, rebuild_rx) = mpsc::channel(1);
+        Self {
+            daemons: Default::default(),
+            rebuild_tx,
+            rebuild_rx: Arc::new(std::sync::Mutex::new(Some(rebuild_rx))),
+        }
+    }
+
+    /// Take the channel which is sent on whenever a sass process finished compiling.
+    pub fn take_rebuild_rx(&self) -> Option<mpsc::Receiver<()>> {
+        self.rebuild_rx.lock().ok()?.take()
+    }
+
+    /// Wait for the output of the sass process compiling to `output` with the given arguments,
+    /// starting it first if it is not running yet.
+    ///
+    /// The output is not awaited for changes which the process did not pick up yet. The build it
+    /// triggers after compiling them does.
+    pub(super) async fn compile(&self, sass: &Path, args: &[&str], output: &Path) -> Result<()> {
+        let mut status = {
+            let mut daemons = self.daemons.lock().await;
+            match daemons.get(output) {
+                Some(daemon) => daemon.status.clone(),
+                None => {
+                    // `--watch` only compiles stale stylesheets at first, removing the output makes
+                    // sure there is a first compilation to wait for.
+                    if fs::metadata(output).await.is_ok() {
+                        fs::remove_file(output)
+                            .await
+                            .context("error removing previous sass output")?;
+                    }
+                    let daemon = SassDaemon::spawn(sass, args, self.rebuild_tx.clone());
+                    let status = daemon.status.clone();
+                    daemons.insert(output.to_owned(), daemon);
+                    status
+                }
+            }
+        };
+
+        while status.borrow().is_none() {
+            status
+                .changed()
+                .await
+                .context("sass process stopped unexpectedly")?;
+        }
+        let result = status.borrow().clone().unwrap_or(Ok(()));
+        result.map_err(|err| anyhow!(err))
+    }
+}
+
+/// A `sass --watch` process, restarted whenever it crashes, and stopped when dropped.
+#[derive(Debug)]
+struct SassDaemon {
+    /// The outcome of the latest compilation.
+    status: watch::Receiver<SassStatus>,
+    /// The task running the process.
+    task: JoinHandle<()>,
+}
+
+impl SassDaemon {
+    fn spawn(sass: &Path, args: &[&str], rebuild_tx: mpsc::Sender<()>) -> Self {
+        let (status_tx, status) = watch::channel(None);
+        let sass = sass.to_owned();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let task = tokio::spawn(async move {
+            loop {
+                if let Err(err) = run_sass(&sass, &args, &status_tx, &rebuild_tx).await {
+                    tracing::error!("error running sass in watch mode: {:#}", err);
+                }
+                tracing::warn!("sass stopped watching, restarting it");
+                tokio::time::sleep(RESTART_DELAY).await;
+            }
+        });
+        Self { status, task }
+    }
+}
+
+impl Drop for SassDaemon {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Run sass until it exits, reporting each compilation it finishes.
+async fn run_sass(
+    sass: &Path,
+    args: &[String],
+    status_tx: &watch::Sender<SassStatus>,
+    rebuild_tx: &mpsc::Sender<()>,
+) -> Result<()> {
+    let mut child = Command::new(sass)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("error spawning sass")?;
+    let mut stdout = BufReader::new(child.stdout.take().context("sass has no stdout")?).lines();
+    let mut stderr = BufReader::new(child.stderr.take().context("sass has no stderr")?).lines();
+
+    // Errors span several lines, starting with one beginning with `Error`.
+    let mut error: Option<String> = None;
+    loop {
+        tokio::select! {
+            line = stdout.next_line() => match line.context("error reading sass output")? {
+                Some(line) if line.starts_with("Compiled") => {
+                    tracing::debug!("{}", line);
+                    error = None;
+                    // The first compilation is awaited by the build starting the process.
+                    if status_tx.send_replace(Some(Ok(()))).is_some() {
+                        let _ = rebuild_tx.try_send(());
+                    }
+                }
+                Some(line) => tracing::debug!("{}", line),
+                None => break,
+            },
+            line = stderr.next_line() => match line.context("error reading sass output")? {
+                Some(line) if line.starts_with("Error") => {
+                    if status_tx.send_replace(Some(Err(line.clone()))).is_some() {
+                        let _ = rebuild_tx.try_send(());
+                    }
+                    error = Some(line);
+                }
+                Some(line) => match error.as_mut() {
+                    Some(error) => {
+                        error.push('\n');
+                        error.push_str(&line);
+                        let _ = status_tx.send(Some(Err(error.clone())));
+                    }
+                    None => tracing::warn!("{}", line),
+                },
+                None => break,
+            },
+        }
+    }
+
+    let status = child.wait().await.context("error waiting for sass")?;
+    tracing::debug!("sass exited with {}", status);
+    Ok(())
+}
@@ -0,0 +1,153 @@
+//! Error overlays showing failed sass compilations in the browser.
+//!
+//! The overlay is a stylesheet covering the page with the error, formatted like the error overlay
+//! of webpack, so it is shown in place of the stylesheet which failed to compile.
+use std::fmt::Write;
+
+/// A sass compilation error, parsed from the messages of sass.
+#[derive(Debug, PartialEq, Eq)]
+struct SassError<'a> {
+    /// The message of the error, like `Undefined variable.`.
+    message: &'a str,
+    /// The stylesheet, line and column of the error, if sass reported it.
+    location: Option<(&'a str, u32, u32)>,
+    /// The lines of the stylesheet sass shows around the error, marking its position.
+    snippet: Vec<&'a str>,
+}
+
+impl<'a> SassError<'a> {
+    /// Parse the messages of a failed sass compilation, which look like:
+    ///
+    /// ```text
+    /// Error: Undefined variable.
+    ///   ╷
+    /// 3 │   color: $primary;
+    ///   │          ^^^^^^^^
+    ///   ╵
+    ///   styles/main.scss 3:10  root stylesheet
+    /// ```
+    ///
+    /// The first line of the stack trace following the snippet is the location of the error.
+    fn parse(messages: &'a str) -> Option<Self> {
+        let mut lines = messages
+            .lines()
+            .skip_while(|line| !line.starts_with("Error: "));
+        let message = lines.next()?.trim_start_matches("Error: ").trim();
+        let mut snippet = vec![];
+        let mut location = None;
+        for line in lines {
+            if let Some(parsed) = parse_location(line) {
+                location = Some(parsed);
+                break;
+            }
+            snippet.push(line);
+        }
+        Some(Self {
+            message,
+            location,
+            snippet,
+        })
+    }
+}
+
+/// Parse a line of the stack trace of sass, like `  styles/main.scss 3:10  root stylesheet`, into
+/// the stylesheet, line and column.
+fn parse_location(line: &str) -> Option<(&str, u32, u32)> {
+    let mut parts = line.split_whitespace();
+    let path = parts.next()?;
+    let (line, column) = parts.next()?.split_once(':')?;
+    Some((path, line.parse().ok()?, column.parse().ok()?))
+}
+
+/// A stylesheet covering the page with the given messages of a failed sass compilation.
+///
+/// Messages which can not be parsed are shown as they are.
+pub(super) fn error_overlay_css(messages: &str) -> String {
+    let mut text = String::from("Compiled with problems:\n\n");
+    match SassError::parse(messages) {
+        Some(error) => {
+            text.push_str("ERROR");
+            if let Some((path, line, column)) = error.location {
+                let _ = write!(text, " in {} {}:{}", path, line, column);
+            }
+            let _ = write!(text, "\n\nSassError: {}\n", error.message);
+            for line in error.snippet {
+                let _ = writeln!(text, "{}", line);
+            }
+        }
+        None => {
+            let _ = writeln!(text, "ERROR\n\n{}", messages.trim());
+        }
+    }
+
+    format!(
+        "body::before {{\n  content: \"{}\";\n  position: fixed;\n  top: 0;\n  left: 0;\n  \
+         width: 100vw;\n  height: 100vh;\n  box-sizing: border-box;\n  overflow: auto;\n  \
+         z-index: 2147483647;\n  padding: 2rem;\n  background-color: rgba(0, 0, 0, 0.85);\n  \
+         color: #e8e8e8;\n  font-family: Menlo, Consolas, monospace;\n  font-size: large;\n  \
+         line-height: 1.2;\n  white-space: pre-wrap;\n}}\n",
+        css_string_content(&text)
+    )
+}
+
+/// Escape the given text for a CSS string in double quotes, keeping its line breaks. Non-ASCII
+/// characters, like the box drawing characters of the snippets of sass, are escaped as well, so
+/// the overlay shows them regardless of the encoding the stylesheet is read with.
+fn css_string_content(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            // The space ends the escape, so it is not mistaken for part of a following hex digit.
+            _ => {
+                let _ = write!(escaped, "\\{:X} ", c as u32);
+            }
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGES: &str = "sass call returned a bad status:
+Error: Undefined variable.
+  ╷
+3 │   color: $primary;
+  │          ^^^^^^^^
+  ╵
+  styles/main.scss 3:10  @use
+  index.scss 1:1         root stylesheet";
+
+    #[test]
+    fn parse_sass_error() {
+        let error = SassError::parse(MESSAGES);
+        assert_eq!(
+            error,
+            Some(SassError {
+                message: "Undefined variable.",
+                location: Some(("styles/main.scss", 3, 10)),
+                snippet: vec![
+                    "  ╷",
+                    "3 │   color: $primary;",
+                    "  │          ^^^^^^^^",
+                    "  ╵"
+                ],
+            })
+        );
+        assert_eq!(SassError::parse("error spawning sass call"), None);
+    }
+
+    #[test]
+    fn sass_error_overlay_css() {
+        let css = error_overlay_css(MESSAGES);
+        assert!(css.starts_with("body::before {\n  content: \"Compiled with problems:\\A \\A ERROR in styles/main.scss 3:10\\A \\A SassError: Undefined variable.\\A   \\2577 \\A 3 \\2502"));
+        assert!(!css.contains("index.scss"));
+        assert_eq!(css_string_content("\"a\\b\""), "\\\"a\\\\b\\\"");
+    }
+}
@@ -0,0 +1,350 @@
+//! Reading the `target_features` and `producers` custom sections of WASM binaries.
+//!
+//! The linker records the WASM proposals the binary was compiled with in the `target_features`
+//! section, like `reference-types` when rustc targets a CPU supporting them. The `producers`
+//! section lists the tools which created or processed the binary, like wasm-bindgen.
+//!
+//! Besides, the overall structure of binaries is checked before handing them to wasm-bindgen,
+//! whose errors about malformed input are hard to make sense of.
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use tokio::fs;
+
+/// The name of the custom section listing the target features.
+const SECTION_NAME: &str = "target_features";
+/// The name of the custom section listing the producers.
+const PRODUCERS_SECTION_NAME: &str = "producers";
+
+/// The features used by a WASM binary.
+#[derive(Debug, Default)]
+pub(super) struct TargetFeatures(HashSet<String>);
+
+impl TargetFeatures {
+    /// Read the target features of the WASM binary at the given path. Binaries without a
+    /// `target_features` section are assumed to use no features.
+    pub(super) async fn read(path: &Path) -> Result<Self> {
+        let wasm = fs::read(path)
+            .await
+            .with_context(|| format!("error reading WASM binary {}", path.display()))?;
+        Self::parse(&wasm)
+            .with_context(|| format!("error reading target features of {}", path.display()))
+    }
+
+    /// Whether the binary uses the given feature, like `reference-types`.
+    pub(super) fn contains(&self, feature: &str) -> bool {
+        self.0.contains(feature)
+    }
+
+    fn parse(wasm: &[u8]) -> Result<Self> {
+        let mut features = HashSet::new();
+        for mut section in custom_sections(wasm, SECTION_NAME)? {
+            for _ in 0..section.leb128()? {
+                // Features are prefixed with `+` if used, `-` if disallowed and `=` if required,
+                // the latter being an outdated form of `+`.
+                let prefix = section.byte()?;
+                let name = section.name()?;
+                if matches!(prefix, b'+' | b'=') {
+                    features.insert(name.to_owned());
+                }
+            }
+        }
+        Ok(Self(features))
+    }
+}
+
+/// The tools which created or processed a WASM binary, by name, with their versions.
+#[derive(Debug, Default)]
+pub(super) struct Producers(HashMap<String, String>);
+
+impl Producers {
+    /// Read the producers of the WASM binary at the given path. Binaries without a `producers`
+    /// section are assumed to have none.
+    pub(super) async fn read(path: &Path) -> Result<Self> {
+        let wasm = fs::read(path)
+            .await
+            .with_context(|| format!("error reading WASM binary {}", path.display()))?;
+        Self::parse(&wasm).with_context(|| format!("error reading producers of {}", path.display()))
+    }
+
+    /// The version of the given tool which processed the binary, like `0.2.92 (b3a5e8d8a)` for
+    /// `wasm-bindgen`.
+    pub(super) fn processed_by(&self, tool: &str) -> Option<&str> {
+        self.0.get(tool).map(String::as_str)
+    }
+
+    fn parse(wasm: &[u8]) -> Result<Self> {
+        let mut producers = HashMap::new();
+        for mut section in custom_sections(wasm, PRODUCERS_SECTION_NAME)? {
+            // The fields are `language`, `processed-by` and `sdk`, each listing tools.
+            for _ in 0..section.leb128()? {
+                let field = section.name()?;
+                for _ in 0..section.leb128()? {
+                    let name = section.name()?;
+                    let version = section.name()?;
+                    if field == "processed-by" {
+                        producers.insert(name.to_owned(), version.to_owned());
+                    }
+                }
+            }
+        }
+        Ok(Self(producers))
+    }
+}
+
+/// Check that the WASM binary at the given path is well-formed: that it starts with the magic bytes
+/// and a supported version, that its known sections appear at most once and in order, and that the
+/// function types in its type section are valid.
+///
+/// This is no full validation like the one of wasm-bindgen, the contents of most sections aren't
+/// checked.
+pub(super) async fn validate_wasm(path: &Path) -> Result<()> {
+    let wasm = fs::read(path)
+        .await
+        .with_context(|| format!("error reading WASM binary {}", path.display()))?;
+    check_structure(&wasm)
+}
+
+fn check_structure(wasm: &[u8]) -> Result<()> {
+    let mut reader = Reader(wasm);
+    ensure!(
+        reader.bytes(4).ok() == Some(&b"\0asm"[..]),
+        "missing the magic bytes `\\0asm`, this is not a WASM binary"
+    );
+    let version = reader.bytes(4)?;
+    ensure!(
+        version == [1, 0, 0, 0],
+        "unsupported WASM binary version {:?}, only version 1 is supported",
+        version
+    );
+
+    let mut previous: Option<(u8, usize)> = None;
+    while !reader.0.is_empty() {
+        let id = reader.byte()?;
+        let size = reader.leb128()? as usize;
+        let mut section = Reader(
+            reader
+                .bytes(size)
+                .with_context(|| format!("section {} exceeds the end of the binary", id))?,
+        );
+        // Custom sections may appear anywhere, any number of times.
+        if id == 0 {
+            section
+                .name()
+                .context("custom section with an invalid name")?;
+            continue;
+        }
+        let position = SECTION_ORDER
+            .iter()
+            .position(|&known| known == id)
+            .with_context(|| format!("unknown section id {}", id))?;
+        if let Some((previous_id, previous_position)) = previous {
+            ensure!(
+                position > previous_position,
+                "section {} follows section {}, sections must appear at most once and in order",
+                id,
+                previous_id
+            );
+        }
+        previous = Some((id, position));
+        if id == TYPE_SECTION_ID {
+            check_types(&mut section).context("invalid type section")?;
+        }
+    }
+    Ok(())
+}
+
+/// The ids of the non-custom sections in the order they have to appear in, the tag section of the
+/// exception handling proposal and the data count section being out of order.
+const SECTION_ORDER: &[u8] = &[1, 2, 3, 4, 5, 13, 6, 7, 8, 9, 12, 10, 11];
+/// The id of the type section.
+const TYPE_SECTION_ID: u8 = 1;
+
+/// Check the function types of a type section. The composite types of the GC proposal end the check,
+/// only function types are checked.
+fn check_types(section: &mut Reader) -> Result<()> {
+    for index in 0..section.leb128()? {
+        match section.byte()? {
+            0x60 => {
+                // The parameter types, followed by the result types.
+                for _ in 0..2 {
+                    for _ in 0..section.leb128()? {
+                        check_value_type(section)
+                            .with_context(|| format!("invalid function type {}", index))?;
+                    }
+                }
+            }
+            // Recursion groups and subtypes, as well as struct and array types.
+            0x4e | 0x4f | 0x50 | 0x5e | 0x5f => return Ok(()),
+            form => bail!("type {} has the unknown form 0x{:02x}", index, form),
+        }
+    }
+    ensure!(section.0.is_empty(), "unexpected bytes after the last type");
+    Ok(())
+}
+
+/// Check a value type, like `i32` or a reference type.
+fn check_value_type(section: &mut Reader) -> Result<()> {
+    match section.byte()? {
+        // Number and vector types, and the shorthands of reference types.
+        0x69..=0x74 | 0x7b..=0x7f => Ok(()),
+        // Reference types, followed by a heap type, which is either a shorthand or a type index.
+        0x63 | 0x64 => match section.0.first() {
+            Some(0x69..=0x74) => section.byte().map(drop),
+            _ => section.leb128().map(drop),
+        },
+        value_type => bail!("unknown value type 0x{:02x}", value_type),
+    }
+}
+
+/// The contents of the custom sections of the given name, following the name.
+fn custom_sections<'a>(wasm: &'a [u8], name: &str) -> Result<Vec<Reader<'a>>> {
+    let mut reader = Reader(wasm);
+    ensure!(reader.bytes(4)? == b"\0asm", "not a WASM binary");
+    ensure!(
+        reader.bytes(4)? == [1, 0, 0, 0],
+        "unsupported WASM binary version"
+    );
+
+    let mut sections = vec![];
+    while !reader.0.is_empty() {
+        let id = reader.byte()?;
+        let size = reader.leb128()? as usize;
+        let mut section = Reader(reader.bytes(size)?);
+        // Only custom sections (id 0) are named.
+        if id == 0 && section.name()? == name {
+            sections.push(section);
+        }
+    }
+    Ok(sections)
+}
+
+/// A cursor over the bytes of a WASM binary.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(len <= self.0.len(), "unexpected end of WASM binary");
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    /// An unsigned LEB128 encoded integer of at most 32 bits.
+    fn leb128(&mut self) -> Result<u32> {
+        let mut value = 0;
+        for shift in (0..35).step_by(7) {
+            let byte = self.byte()?;
+            value |= u32::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("invalid integer in WASM binary")
+    }
+
+    /// A UTF-8 string, prefixed with its length.
+    fn name(&mut self) -> Result<&'a str> {
+        let len = self.leb128()? as usize;
+        std::str::from_utf8(self.bytes(len)?).context("invalid name in WASM binary")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_target_features() -> Result<()> {
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        // An empty type section, which is skipped.
+        wasm.extend([1, 1, 0]);
+        let mut section = vec![SECTION_NAME.len() as u8];
+        section.extend(SECTION_NAME.as_bytes());
+        section.push(3);
+        for (prefix, name) in [
+            ("+", "multivalue"),
+            ("-", "simd128"),
+            ("=", "reference-types"),
+        ] {
+            section.extend(prefix.as_bytes());
+            section.push(name.len() as u8);
+            section.extend(name.as_bytes());
+        }
+        wasm.push(0);
+        wasm.push(section.len() as u8);
+        wasm.extend(section);
+
+        let features = TargetFeatures::parse(&wasm)?;
+        assert!(features.contains("multivalue"));
+        assert!(features.contains("reference-types"));
+        assert!(!features.contains("simd128"));
+
+        assert!(!TargetFeatures::parse(b"\0asm\x01\0\0\0")?.contains("multivalue"));
+        assert!(TargetFeatures::parse(b"\0asm\x01\0\0\0\0\x05").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn parse_producers() -> Result<()> {
+        let push_name = |bytes: &mut Vec<u8>, name: &str| {
+            bytes.push(name.len() as u8);
+            bytes.extend(name.as_bytes());
+        };
+        let mut section = vec![];
+        push_name(&mut section, PRODUCERS_SECTION_NAME);
+        section.push(2);
+        for (field, name, version) in [
+            ("language", "Rust", ""),
+            ("processed-by", "wasm-bindgen", "0.2.92"),
+        ] {
+            push_name(&mut section, field);
+            // Each field lists a single tool.
+            section.push(1);
+            push_name(&mut section, name);
+            push_name(&mut section, version);
+        }
+        let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+        wasm.push(0);
+        wasm.push(section.len() as u8);
+        wasm.extend(section);
+
+        let producers = Producers::parse(&wasm)?;
+        assert_eq!(producers.processed_by("wasm-bindgen"), Some("0.2.92"));
+        assert_eq!(producers.processed_by("Rust"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn check_wasm_structure() -> Result<()> {
+        let wasm = |sections: &[u8]| {
+            let mut wasm = b"\0asm\x01\0\0\0".to_vec();
+            wasm.extend(sections);
+            wasm
+        };
+        // A type section with `(func (param i32) (result f64))`, an empty function section and a
+        // custom section.
+        let valid = [1, 6, 1, 0x60, 1, 0x7f, 1, 0x7c, 3, 1, 0, 0, 2, 1, b'x'];
+        check_structure(&wasm(&valid))?;
+        // A recursion group of the GC proposal.
+        check_structure(&wasm(&[1, 3, 1, 0x4e, 0]))?;
+
+        assert!(check_structure(b"\0wat\x01\0\0\0").is_err());
+        assert!(check_structure(b"\0asm\x02\0\0\0").is_err());
+        // Sections out of order, duplicated or with an unknown id.
+        assert!(check_structure(&wasm(&[3, 1, 0, 1, 1, 0])).is_err());
+        assert!(check_structure(&wasm(&[1, 1, 0, 1, 1, 0])).is_err());
+        assert!(check_structure(&wasm(&[14, 0])).is_err());
+        // A section exceeding the end of the binary.
+        assert!(check_structure(&wasm(&[1, 5, 0])).is_err());
+        // An unknown type form and value type.
+        assert!(check_structure(&wasm(&[1, 2, 1, 0x61])).is_err());
+        assert!(check_structure(&wasm(&[1, 4, 1, 0x60, 1, 0x42])).is_err());
+        Ok(())
+    }
+}
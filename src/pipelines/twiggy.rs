@@ -0,0 +1,60 @@
+//! Code size profiling of WASM files with twiggy.
+//!
+//! twiggy has no release binaries to download, so it is taken from the `PATH`, as installed with
+//! `cargo install twiggy`.
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use serde::Deserialize;
+use tokio::process::Command;
+
+/// The number of items listed by the size profile.
+const SIZE_PROFILE_ITEMS: usize = 20;
+
+/// An item of the output of `twiggy top`.
+#[derive(Debug, Deserialize)]
+struct TopItem {
+    /// The name of the item, like a function or a data segment.
+    name: String,
+    /// The size of the item itself, in bytes.
+    shallow_size: u64,
+    /// The share of the item in the size of the WASM file, in percent.
+    shallow_size_percent: f64,
+}
+
+/// Log the largest items of the given WASM file, as listed by `twiggy top`.
+pub(super) async fn log_size_profile(wasm: &Path) -> Result<()> {
+    let twiggy = which::which("twiggy").context(
+        "`wasm_size_profile` is enabled, but twiggy was not found; install it with \
+         `cargo install twiggy`",
+    )?;
+    let output = Command::new(twiggy)
+        .arg("top")
+        .arg(format!("-n={}", SIZE_PROFILE_ITEMS))
+        .arg("--format=json")
+        .arg(wasm)
+        .output()
+        .await
+        .context("error spawning twiggy")?;
+    ensure!(
+        output.status.success(),
+        "error profiling the code size of {} with twiggy: {}",
+        wasm.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    let items: Vec<TopItem> =
+        serde_json::from_slice(&output.stdout).context("error reading the output of twiggy")?;
+
+    let wasm = wasm.file_name().unwrap_or_default().to_string_lossy();
+    tracing::info!("largest items of {}:", wasm);
+    // twiggy summarizes the remaining items, and reports the total, as items of their own.
+    for item in items {
+        tracing::info!(
+            "{:>10} bytes {:>6.2}% {}",
+            item.shallow_size,
+            item.shallow_size_percent,
+            item.name
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,189 @@
+//! Post-processing of the JS glue code generated by wasm-bindgen.
+//!
+//! This is no full analysis of the JS code. It relies on the layout of wasm-bindgen's output, in
+//! which every top-level function and class starts at the beginning of a line and ends with a
+//! line consisting of a closing brace only.
+
+/// Remove the top-level functions and classes of the given glue code of the `web` target which
+/// are never referenced, including exported ones, returning the remaining code and the names of
+/// the removed declarations.
+///
+/// Exports are only used by the loader script of Trunk, which imports the default export, so any
+/// other export counts as unused unless the glue code references it itself.
+pub(super) fn remove_unused_exports(js: &str) -> (String, Vec<String>) {
+    let mut lines: Vec<&str> = js.lines().collect();
+    let mut removed = vec![];
+    // Removing a declaration may leave the ones it referenced unused.
+    loop {
+        let unused = declarations(&lines)
+            .into_iter()
+            .find(|decl| references(&lines, decl) == 0);
+        match unused {
+            Some(decl) => {
+                removed.push(decl.name.to_owned());
+                let (start, end) = (decl.doc_start, decl.end);
+                // Drop the blank line separating the declaration from the next one as well.
+                let end = match lines.get(end + 1) {
+                    Some(line) if line.trim().is_empty() => end + 1,
+                    _ => end,
+                };
+                lines.drain(start..=end);
+            }
+            None => break,
+        }
+    }
+
+    let mut js_out = lines.join("\n");
+    if js.ends_with('\n') {
+        js_out.push('\n');
+    }
+    (js_out, removed)
+}
+
+/// A top-level function or class declaration of the glue code.
+#[derive(Debug)]
+struct Declaration<'a> {
+    /// The name of the function or class.
+    name: &'a str,
+    /// The index of the first line of the declaration.
+    start: usize,
+    /// The index of the first line of the doc comment preceding the declaration, if any, or else
+    /// the first line of the declaration.
+    doc_start: usize,
+    /// The index of the line closing the declaration.
+    end: usize,
+}
+
+/// The prefixes of top-level declarations which can be removed, followed by their name.
+const DECLARATION_PREFIXES: &[&str] = &[
+    "export function ",
+    "export async function ",
+    "export class ",
+    "function ",
+    "async function ",
+    "class ",
+];
+
+/// The top-level function and class declarations of the given lines.
+fn declarations<'a>(lines: &[&'a str]) -> Vec<Declaration<'a>> {
+    let mut declarations = vec![];
+    for (start, line) in lines.iter().enumerate() {
+        let name = match DECLARATION_PREFIXES
+            .iter()
+            .find_map(|prefix| line.strip_prefix(prefix))
+        {
+            Some(rest) => rest
+                .split(|c: char| !is_ident_char(c))
+                .next()
+                .unwrap_or_default(),
+            None => continue,
+        };
+        if name.is_empty() {
+            continue;
+        }
+        // Declarations without a closing line of their own are left alone.
+        let end = match lines[start..].iter().position(|line| *line == "}") {
+            Some(offset) => start + offset,
+            None => continue,
+        };
+        let doc_start = match start.checked_sub(1).map(|prev| lines[prev].trim()) {
+            Some("*/") => lines[..start]
+                .iter()
+                .rposition(|line| line.trim_start().starts_with("/**"))
+                .unwrap_or(start),
+            _ => start,
+        };
+        declarations.push(Declaration {
+            name,
+            start,
+            doc_start,
+            end,
+        });
+    }
+    declarations
+}
+
+/// The number of references to the given declaration outside of itself.
+fn references(lines: &[&str], decl: &Declaration) -> usize {
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !(decl.start..=decl.end).contains(index))
+        .map(|(_, line)| count_ident(line, decl.name))
+        .sum()
+}
+
+/// The number of occurrences of the given identifier in the line, as a whole word.
+fn count_ident(line: &str, ident: &str) -> usize {
+    line.match_indices(ident)
+        .filter(|(index, _)| {
+            let before = line[..*index].chars().next_back();
+            let after = line[index + ident.len()..].chars().next();
+            !before.map(is_ident_char).unwrap_or(false)
+                && !after.map(is_ident_char).unwrap_or(false)
+        })
+        .count()
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remove_unused_glue_exports() {
+        let js = r#"let wasm;
+
+function passString(arg) {
+    return arg;
+}
+
+function unusedHelper() {
+    return 1;
+}
+
+/**
+* @param {string} name
+*/
+export function greet(name) {
+    wasm.greet(passString(name));
+}
+
+export function start() {
+    wasm.start();
+}
+
+function __wbg_get_imports() {
+    const imports = {};
+    imports.wbg.__wbg_start = function() { start(); };
+    return imports;
+}
+
+async function __wbg_init(input) {
+    const imports = __wbg_get_imports();
+}
+
+export default __wbg_init;
+"#;
+        let (js, removed) = remove_unused_exports(js);
+        assert_eq!(removed, ["unusedHelper", "greet", "passString"]);
+        // Exports referenced by the glue code itself are kept, along with the default export.
+        assert!(js.contains("export function start()"));
+        assert!(js.contains("async function __wbg_init(input)"));
+        assert!(!js.contains("@param"));
+        assert!(js.starts_with("let wasm;\n\nexport function start() {"));
+        assert!(js.ends_with("export default __wbg_init;\n"));
+    }
+
+    #[test]
+    fn count_whole_identifiers() {
+        assert_eq!(
+            count_ident("greet(greeting, $greet, greet_2, greet)", "greet"),
+            2
+        );
+        assert_eq!(count_ident("export { initSync };", "initSync"), 1);
+    }
+}
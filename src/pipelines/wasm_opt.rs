@@ -0,0 +1,102 @@
+//! wasm-opt asset pipeline, for pre-compiled WASM files.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use nipper::Document;
+use tokio::task::JoinHandle;
+
+use super::rust::{profile_inherits_release, run_wasm_opt, WasmOptConfig};
+use super::target_features::TargetFeatures;
+use super::{AssetFile, Attrs, TrunkAssetPipelineOutput, ATTR_HREF};
+use crate::config::RtcBuild;
+
+/// A pipeline optimizing a pre-compiled WASM file with wasm-opt, like the output of Emscripten or
+/// AssemblyScript.
+///
+/// Unlike the Rust app pipeline, wasm-bindgen is not involved. The file keeps its name, as it is
+/// loaded by the application's own JS code.
+pub struct WasmOptOnlyPipeline {
+    /// The ID of this pipeline's source HTML element.
+    id: usize,
+    /// Runtime build config.
+    cfg: Arc<RtcBuild>,
+    /// The asset file being processed.
+    asset: AssetFile,
+    /// The wasm-opt settings.
+    wasm_opt: WasmOptConfig,
+}
+
+impl WasmOptOnlyPipeline {
+    pub const TYPE_WASM_OPT: &'static str = "wasm-opt";
+
+    pub async fn new(
+        cfg: Arc<RtcBuild>,
+        html_dir: Arc<PathBuf>,
+        attrs: Attrs,
+        id: usize,
+    ) -> Result<Self> {
+        // Build the path to the target asset.
+        let href_attr = attrs.get(ATTR_HREF).context(
+            r#"required attr `href` missing for <link data-trunk rel="wasm-opt" .../> element"#,
+        )?;
+        let mut path = PathBuf::new();
+        path.extend(href_attr.split('/'));
+        let asset = AssetFile::new(&html_dir, path).await?;
+        // The profiles are looked up in the manifest next to the source HTML file, as there is
+        // no Rust app to take the workspace from.
+        let inherits_release = cfg.release
+            || profile_inherits_release(cfg.cargo_profile(), &html_dir.join("Cargo.toml"));
+        let profile = WasmOptConfig::for_profile(
+            cfg.cargo_profile(),
+            &cfg.wasm_opt_profiles,
+            inherits_release,
+        );
+        let wasm_opt = WasmOptConfig::from_attrs(&attrs, cfg.release, profile, false)?;
+        Ok(Self {
+            id,
+            cfg,
+            asset,
+            wasm_opt,
+        })
+    }
+
+    /// Spawn the pipeline for this asset type.
+    #[tracing::instrument(level = "trace", skip(self))]
+    pub fn spawn(self) -> JoinHandle<Result<TrunkAssetPipelineOutput>> {
+        tokio::spawn(self.run())
+    }
+
+    /// Run this pipeline.
+    #[tracing::instrument(level = "trace", skip(self))]
+    async fn run(mut self) -> Result<TrunkAssetPipelineOutput> {
+        let rel_path = crate::common::strip_prefix(&self.asset.path);
+        // Same as for Rust apps, wasm-opt can be disabled with `--no-opt`.
+        if !self.cfg.no_opt && self.wasm_opt.is_enabled() {
+            tracing::info!(path = ?rel_path, "optimizing wasm file");
+            let features = TargetFeatures::read(&self.asset.path).await?;
+            self.wasm_opt.detect_features(&features);
+            let output = self.cfg.staging_dist.join(&self.asset.file_name);
+            run_wasm_opt(&self.cfg, &self.wasm_opt, &self.asset.path, &output, &[]).await?;
+            tracing::info!(path = ?rel_path, "finished optimizing wasm file");
+        } else {
+            tracing::info!(path = ?rel_path, "copying wasm file");
+            let _ = self.asset.copy(&self.cfg.staging_dist, false).await?;
+            tracing::info!(path = ?rel_path, "finished copying wasm file");
+        }
+        Ok(TrunkAssetPipelineOutput::WasmOpt(WasmOptOnlyOutput(
+            self.id,
+        )))
+    }
+}
+
+/// The output of a wasm-opt build pipeline.
+pub struct WasmOptOnlyOutput(usize);
+
+impl WasmOptOnlyOutput {
+    pub async fn finalize(self, dom: &mut Document) -> Result<()> {
+        dom.select(&super::trunk_id_selector(self.0)).remove();
+        Ok(())
+    }
+}
@@ -2,19 +2,32 @@
 //! applications (if needed) to use them in the build pipeline.
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, ensure, Context, Result};
+use bytes::Bytes;
 use directories::ProjectDirs;
-use futures_util::stream::StreamExt;
+use futures_util::future::Future;
+use futures_util::ready;
+use futures_util::stream::{Stream, StreamExt};
 use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
-use tokio::sync::{Mutex, OnceCell};
+use tokio::sync::{watch, Mutex, OnceCell};
 
-use self::archive::Archive;
-use crate::common::is_executable;
+use self::archive::{Archive, ArchiveFormat};
+use crate::common::{self, is_executable, path_exists};
+use crate::config::{ConfigOptsToolsDownloadUrls, ConfigOptsToolsProxy};
 
 /// The application to locate and eventually download when calling [`get`].
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -24,37 +37,56 @@ pub enum Application {
     WasmBindgen,
     /// wasm-opt to improve performance and size of the output file further.
     WasmOpt,
+    /// wasm-tools for inspecting WASM components.
+    WasmTools,
+    /// wizer for pre-initializing WASM modules at build time.
+    Wizer,
 }
 
 impl Application {
+    /// All applications that can be downloaded.
+    pub(crate) const ALL: [Self; 5] = [
+        Self::Sass,
+        Self::WasmBindgen,
+        Self::WasmOpt,
+        Self::WasmTools,
+        Self::Wizer,
+    ];
+
     /// Base name of the executable without extension.
     pub(crate) fn name(&self) -> &str {
         match self {
             Self::Sass => "sass",
             Self::WasmBindgen => "wasm-bindgen",
             Self::WasmOpt => "wasm-opt",
+            Self::WasmTools => "wasm-tools",
+            Self::Wizer => "wizer",
         }
     }
 
     /// Path of the executable within the downloaded archive.
-    fn path(&self) -> &str {
+    fn path(&self) -> &'static str {
         if cfg!(target_os = "windows") {
             match self {
                 Self::Sass => "sass.bat",
                 Self::WasmBindgen => "wasm-bindgen.exe",
                 Self::WasmOpt => "bin/wasm-opt.exe",
+                Self::WasmTools => "wasm-tools.exe",
+                Self::Wizer => "wizer.exe",
             }
         } else {
             match self {
                 Self::Sass => "sass",
                 Self::WasmBindgen => "wasm-bindgen",
                 Self::WasmOpt => "bin/wasm-opt",
+                Self::WasmTools => "wasm-tools",
+                Self::Wizer => "wizer",
             }
         }
     }
 
     /// Additional files included in the archive that are required to run the main binary.
-    fn extra_paths(&self) -> &[&str] {
+    fn extra_paths(&self) -> &'static [&'static str] {
         match self {
             Self::Sass => {
                 if cfg!(target_os = "windows") {
@@ -65,7 +97,7 @@ impl Application {
                     &[]
                 }
             }
-            Self::WasmBindgen => &[],
+            Self::WasmBindgen | Self::WasmTools | Self::Wizer => &[],
             Self::WasmOpt => {
                 if cfg!(target_os = "macos") {
                     &["lib/libbinaryen.dylib"]
@@ -76,17 +108,34 @@ impl Application {
         }
     }
 
+    /// The known SHA-256 digest of the release archive of the given version, for the current
+    /// platform, as hex string.
+    pub(crate) fn checksum(&self, version: &str) -> Option<&'static str> {
+        let url = self.url(version).ok()?;
+        KNOWN_CHECKSUMS
+            .iter()
+            .find(|(known_url, _)| *known_url == url)
+            .map(|(_, digest)| *digest)
+    }
+
     /// Default version to use if not set by the user.
-    fn default_version(&self) -> &str {
+    pub(crate) fn default_version(&self) -> &str {
         match self {
-            Self::Sass => "1.54.9",
+            Self::Sass => "1.71.0",
             Self::WasmBindgen => "0.2.83",
             Self::WasmOpt => "version_110",
+            Self::WasmTools => "1.200.0",
+            Self::Wizer => "3.0.1",
         }
     }
 
-    /// Direct URL to the release of an application for download.
-    fn url(&self, version: &str) -> Result<String> {
+    /// Direct URL to the release of an application for download, or the URL configured in the
+    /// `[tools.download_urls]` section of `Trunk.toml`, see [`init_download_urls`].
+    pub(crate) fn url(&self, version: &str) -> Result<String> {
+        if let Some(url) = DOWNLOAD_URLS.get().and_then(|urls| self.download_url(urls)) {
+            return Ok(url.replace("{version}", version));
+        }
+
         let target_os = if cfg!(target_os = "windows") {
             "windows"
         } else if cfg!(target_os = "macos") {
@@ -105,6 +154,22 @@ impl Application {
             bail!("unsupported target architecture")
         };
 
+        self.url_for(version, target_os, target_arch)
+    }
+
+    /// The URL configured for downloading the application, if any.
+    fn download_url<'a>(&self, urls: &'a ConfigOptsToolsDownloadUrls) -> Option<&'a str> {
+        match self {
+            Self::Sass => urls.sass.as_deref(),
+            Self::WasmBindgen => urls.wasm_bindgen.as_deref(),
+            Self::WasmOpt => urls.wasm_opt.as_deref(),
+            Self::WasmTools => urls.wasm_tools.as_deref(),
+            Self::Wizer => urls.wizer.as_deref(),
+        }
+    }
+
+    /// Direct URL to the release of an application for the given OS and architecture.
+    fn url_for(&self, version: &str, target_os: &str, target_arch: &str) -> Result<String> {
         Ok(match self {
             Self::Sass => match (target_os, target_arch) {
               ("windows", "x86_64") => format!("https://github.com/sass/dart-sass/releases/download/{version}/dart-sass-{version}-windows-x64.zip"),
@@ -114,66 +179,248 @@ impl Application {
             },
 
             Self::WasmBindgen => format!(
-                "https://github.com/rustwasm/wasm-bindgen/releases/download/{version}/wasm-bindgen-{version}-x86_64-{os}.tar.gz",
-                os = match target_os {
-                "windows" => "pc-windows-msvc",
-                "macos" => "apple-darwin",
-                "linux" => "unknown-linux-musl",
-                _ => unreachable!(),
+                "https://github.com/rustwasm/wasm-bindgen/releases/download/{version}/wasm-bindgen-{version}-{target}.tar.gz",
+                target = match (target_os, target_arch) {
+                ("windows", _) => "x86_64-pc-windows-msvc",
+                ("macos", _) => "x86_64-apple-darwin",
+                ("linux", "aarch64") => "aarch64-unknown-linux-musl",
+                ("linux", _) => "x86_64-unknown-linux-musl",
+                _ => bail!("Unable to download wasm-bindgen for {target_os} {target_arch}"),
               }),
 
             Self::WasmOpt => match (target_os, target_arch) {
               ("macos", "aarch64") => format!("https://github.com/WebAssembly/binaryen/releases/download/{version}/binaryen-{version}-arm64-macos.tar.gz"),
               _ => format!("https://github.com/WebAssembly/binaryen/releases/download/{version}/binaryen-{version}-{target_arch}-{target_os}.tar.gz")
-            }
+            },
+
+            Self::WasmTools => format!(
+                "https://github.com/bytecodealliance/wasm-tools/releases/download/v{version}/wasm-tools-{version}-{target_arch}-{target_os}.{ext}",
+                ext = if target_os == "windows" { "zip" } else { "tar.gz" }
+            ),
+
+            Self::Wizer => format!(
+                "https://github.com/bytecodealliance/wizer/releases/download/v{version}/wizer-v{version}-{target_arch}-{target_os}.{ext}",
+                ext = if target_os == "windows" { "zip" } else { "tar.xz" }
+            ),
         })
     }
 
+    /// The GitHub repository releasing the application, as `owner/repo`.
+    fn github_repo(&self) -> &'static str {
+        match self {
+            Self::Sass => "sass/dart-sass",
+            Self::WasmBindgen => "rustwasm/wasm-bindgen",
+            Self::WasmOpt => "WebAssembly/binaryen",
+            Self::WasmTools => "bytecodealliance/wasm-tools",
+            Self::Wizer => "bytecodealliance/wizer",
+        }
+    }
+
+    /// The Git tag of the release of the given version.
+    fn release_tag(&self, version: &str) -> String {
+        match self {
+            Self::WasmTools | Self::Wizer => format!("v{}", version),
+            _ => version.to_owned(),
+        }
+    }
+
     /// The CLI subcommand, flag or option used to check the application's version.
     fn version_test(&self) -> &'static str {
         match self {
             Application::Sass => "--version",
             Application::WasmBindgen => "--version",
             Application::WasmOpt => "--version",
+            Application::WasmTools => "--version",
+            Application::Wizer => "--version",
         }
     }
 
+    /// Format of the release archive of the given version, as told by the extension of its URL.
+    fn archive_format(&self, version: &str) -> Result<ArchiveFormat> {
+        ArchiveFormat::from_file_name(&self.url(version)?)
+    }
+
     /// Format the output of version checking the app.
+    ///
+    /// Self-compiled tools may carry additional build information in their version, like a
+    /// `-dirty` marker for builds from a modified worktree or `+` build metadata. These are
+    /// stripped, so the result still compares equal to the release version configured by the
+    /// user. Pre-release suffixes like `-preview.0` on the other hand are preserved, as a
+    /// pre-release is a distinct version that only matches when requested explicitly.
     fn format_version_output(&self, text: &str) -> Result<String> {
         let text = text.trim();
-        let formatted_version = match self {
-            Application::Sass => text
-                .lines()
-                .next()
-                .with_context(|| format!("missing or malformed version output: {}", text))?
-                .to_owned(),
-            Application::WasmBindgen => text
-                .split(' ')
-                .nth(1)
-                .with_context(|| format!("missing or malformed version output: {}", text))?
-                .to_owned(),
-            Application::WasmOpt => format!(
-                "version_{}",
-                text.split(' ')
-                    .nth(2)
-                    .with_context(|| format!("missing or malformed version output: {}", text))?
-            ),
-        };
+        let formatted_version =
+            match self {
+                Application::Sass => {
+                    strip_build_suffix(text.lines().next().with_context(|| {
+                        format!("missing or malformed version output: {}", text)
+                    })?)
+                    .to_owned()
+                }
+                Application::WasmBindgen | Application::WasmTools | Application::Wizer => {
+                    strip_build_suffix(text.split(' ').nth(1).with_context(|| {
+                        format!("missing or malformed version output: {}", text)
+                    })?)
+                    .to_owned()
+                }
+                Application::WasmOpt => format!(
+                    "version_{}",
+                    strip_build_suffix(text.split(' ').nth(2).with_context(|| format!(
+                        "missing or malformed version output: {}",
+                        text
+                    ))?)
+                ),
+            };
         Ok(formatted_version)
     }
 }
 
+impl FromStr for Application {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|app| app.name() == s)
+            .with_context(|| format!("unknown application `{}`", s))
+    }
+}
+
+/// Strip build information of self-compiled tools from a version string, namely `+` build
+/// metadata and a trailing `-dirty` marker.
+fn strip_build_suffix(version: &str) -> &str {
+    let version = version.split('+').next().unwrap_or(version);
+    version.strip_suffix("-dirty").unwrap_or(version)
+}
+
+/// The URLs configured for downloading tools, see [`init_download_urls`].
+static DOWNLOAD_URLS: once_cell::sync::OnceCell<ConfigOptsToolsDownloadUrls> =
+    once_cell::sync::OnceCell::new();
+
+/// Configure the URLs to download tools from instead of their GitHub releases, like the ones of a
+/// mirror.
+///
+/// Only the first call has an effect.
+pub fn init_download_urls(urls: Option<&ConfigOptsToolsDownloadUrls>) {
+    let _ = DOWNLOAD_URLS.set(urls.cloned().unwrap_or_default());
+}
+
+/// Whether tools may only be taken from the system and the cache, see [`init_offline`].
+static OFFLINE: once_cell::sync::OnceCell<bool> = once_cell::sync::OnceCell::new();
+
+/// Only use system installed and previously downloaded tools, failing if one is missing instead of
+/// downloading it.
+///
+/// Only the first call has an effect.
+pub fn init_offline(offline: bool) {
+    let _ = OFFLINE.set(offline);
+}
+
+/// The client for all requests made for downloading tools, see [`init_http_client`].
+static HTTP_CLIENT: once_cell::sync::OnceCell<reqwest::Client> = once_cell::sync::OnceCell::new();
+
+/// Configure the client for all requests made for downloading tools, using the given proxy if
+/// any is configured, or else the proxy of the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` env
+/// vars.
+///
+/// Only the first call has an effect.
+pub fn init_http_client(proxy: Option<&ConfigOptsToolsProxy>) -> Result<()> {
+    let env_proxy = env_proxy(|name| std::env::var(name).ok());
+    let proxy = proxy.filter(|proxy| proxy.is_configured());
+    let client = build_http_client(proxy.unwrap_or(&env_proxy))?;
+    let _ = HTTP_CLIENT.set(client);
+    Ok(())
+}
+
+/// The client for all requests made for downloading tools, without a proxy configured in
+/// `Trunk.toml` unless [`init_http_client`] was called before.
+fn http_client() -> reqwest::Client {
+    HTTP_CLIENT
+        .get_or_init(|| {
+            let proxy = env_proxy(|name| std::env::var(name).ok());
+            build_http_client(&proxy).unwrap_or_else(|err| {
+                tracing::warn!("{:?}, downloading tools without a proxy", err);
+                reqwest::Client::new()
+            })
+        })
+        .clone()
+}
+
+/// Build a client sending requests through the given proxy, if any is configured.
+fn build_http_client(proxy: &ConfigOptsToolsProxy) -> Result<reqwest::Client> {
+    // The proxy replaces the ones reqwest reads from the env vars, which lack `NO_PROXY` support.
+    let mut builder = reqwest::Client::builder().no_proxy();
+    if proxy.is_configured() {
+        builder = builder.proxy(tools_proxy(proxy)?);
+    }
+    builder.build().context("error building HTTP client")
+}
+
+/// The proxy of the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` env vars, or their lowercase
+/// variants, read with the given function.
+fn env_proxy(var: impl Fn(&str) -> Option<String>) -> ConfigOptsToolsProxy {
+    let var = |name: &str| {
+        var(name)
+            .or_else(|| var(&name.to_ascii_lowercase()))
+            .filter(|value| !value.trim().is_empty())
+    };
+    ConfigOptsToolsProxy {
+        http_url: var("HTTP_PROXY"),
+        https_url: var("HTTPS_PROXY"),
+        no_proxy: var("NO_PROXY"),
+    }
+}
+
+/// The proxy configured in the `[tools.proxy]` section of `Trunk.toml`.
+fn tools_proxy(config: &ConfigOptsToolsProxy) -> Result<reqwest::Proxy> {
+    let parse = |url: &Option<String>| {
+        url.as_deref()
+            .map(|url| {
+                reqwest::Url::parse(url).with_context(|| format!("invalid proxy URL `{}`", url))
+            })
+            .transpose()
+    };
+    let http_url = parse(&config.http_url)?;
+    let https_url = parse(&config.https_url)?;
+    let no_proxy = config.no_proxy.clone().unwrap_or_default();
+    Ok(reqwest::Proxy::custom(move |url| {
+        if matches!(url.host_str(), Some(host) if bypasses_proxy(&no_proxy, host)) {
+            return None;
+        }
+        match url.scheme() {
+            "http" => http_url.clone(),
+            "https" => https_url.clone(),
+            _ => None,
+        }
+    }))
+}
+
+/// Whether the given host is listed in the comma separated `no_proxy` list, either itself, as
+/// subdomain of a listed domain, or by a `*` entry.
+fn bypasses_proxy(no_proxy: &str, host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().to_ascii_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            let domain = entry.trim_start_matches('.');
+            entry == "*" || host == domain || host.ends_with(&format!(".{}", domain))
+        })
+}
+
 /// Global, application wide app cache that keeps track of what tools have already been
 /// downloaded and installed to avoid duplicate installation runs.
 static GLOBAL_APP_CACHE: Lazy<Mutex<AppCache>> = Lazy::new(|| Mutex::new(AppCache::new()));
 
-/// An app cache that does the actual download and installation of tools while keeping track of
-/// what has already been installed in the current trunk execution.
+/// An app cache that keeps track of what has already been installed in the current trunk
+/// execution.
 ///
 /// This cache doesn't keep track of any system-installed tools or the one's that have been
 /// installed in previous runs of trunk. It only helps in avoiding a download of the same tool
 /// concurrently during a single run of trunk.
-struct AppCache(HashMap<(Application, String), OnceCell<()>>);
+struct AppCache(HashMap<(Application, String), Arc<OnceCell<()>>>);
 
 impl AppCache {
     /// Create a new app cache.
@@ -181,43 +428,75 @@ impl AppCache {
         Self(HashMap::new())
     }
 
-    /// Install the desired application of given version to the provided application directory. Or
-    /// don't if it's already been installed.
-    async fn install_once(
-        &mut self,
-        app: Application,
-        version: &str,
-        app_dir: PathBuf,
-    ) -> Result<()> {
-        let cached = self
-            .0
-            .entry((app, version.to_owned()))
-            .or_insert_with(OnceCell::new);
-
-        cached
-            .get_or_try_init(|| async move {
-                let path = download(app, version)
-                    .await
-                    .context("failed downloading release archive")?;
+    /// The installation of the given application version, shared by everyone installing it, see
+    /// [`install_once`]. The cache only needs to be locked for getting it, so different
+    /// applications and versions are installed concurrently.
+    fn installation(&mut self, app: Application, version: &str) -> Arc<OnceCell<()>> {
+        self.0.entry((app, version.to_owned())).or_default().clone()
+    }
+}
 
-                let file = File::open(&path)
-                    .await
-                    .context("failed opening downloaded file")?;
-                install(app, file, app_dir).await?;
-                tokio::fs::remove_file(path)
+/// Install the desired application of given version to the provided application directory. Or
+/// don't if it's already been installed through the given installation.
+///
+/// Only one installation attempt runs at a time, and a failed attempt leaves the installation
+/// uninitialized, so calling this again retries it.
+async fn install_once(
+    installation: &OnceCell<()>,
+    app: Application,
+    version: &str,
+    app_dir: PathBuf,
+    config: &DownloadConfig,
+    progress: &watch::Sender<DownloadProgress>,
+) -> Result<()> {
+    installation
+        .get_or_try_init(|| async move {
+            let path = download(app, version, config, progress)
+                .await
+                .context("failed downloading release archive")?;
+
+            // A previous attempt may have left a partial installation behind.
+            if path_exists(&app_dir).await? {
+                common::remove_dir_all(app_dir.clone())
                     .await
-                    .context("failed deleting temporary archive")?;
+                    .context("failed removing partially installed application")?;
+            }
+            let installed = install(app, version, path.clone(), app_dir)
+                .await
+                .context(InstallFailed);
+            tokio::fs::remove_file(path)
+                .await
+                .context("failed deleting temporary archive")?;
 
-                Ok(())
-            })
-            .await
-            .map(|_| ())
+            installed
+        })
+        .await
+        .map(|_| ())
+}
+
+/// Marker for errors while installing a downloaded archive. These are most likely caused by a
+/// corrupt download, and therefore worth retrying.
+#[derive(Debug)]
+struct InstallFailed;
+
+impl fmt::Display for InstallFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("failed installing release archive")
     }
 }
 
-/// Locate the given application and download it if missing.
-#[tracing::instrument(level = "trace")]
+/// How often the download of an application is retried, if installing it fails.
+const INSTALL_RETRIES: usize = 2;
+
+/// Locate the given application and download it if missing, unless in offline mode, see
+/// [`init_offline`].
 pub async fn get(app: Application, version: Option<&str>) -> Result<PathBuf> {
+    get_with(app, version, OFFLINE.get().copied().unwrap_or(false)).await
+}
+
+/// Locate the given application and download it if missing and not `offline`.
+#[tracing::instrument(level = "trace")]
+async fn get_with(app: Application, version: Option<&str>, offline: bool) -> Result<PathBuf> {
     if let Some((path, version)) = find_system(app, version).await {
         tracing::info!(app = %app.name(), %version, "using system installed binary");
         return Ok(path);
@@ -225,20 +504,102 @@ pub async fn get(app: Application, version: Option<&str>) -> Result<PathBuf> {
 
     let cache_dir = cache_dir().await?;
     let version = version.unwrap_or_else(|| app.default_version());
+    // Ranges select among the installed versions, but don't name a release to download.
+    ensure!(
+        !is_version_range(version),
+        "no system installed {} matches the version range `{}`, configure an exact version to \
+         download it instead",
+        app.name(),
+        version
+    );
     let app_dir = cache_dir.join(format!("{}-{}", app.name(), version));
     let bin_path = app_dir.join(app.path());
 
     if !is_executable(&bin_path).await? {
-        GLOBAL_APP_CACHE
-            .lock()
+        ensure!(
+            !offline,
+            "{} {} is neither installed on the system nor downloaded to {}, and can not be \
+             downloaded in offline mode; run Trunk once without `--offline` to download it",
+            app.name(),
+            version,
+            cache_dir.display()
+        );
+        let config = DownloadConfig::from_env()?;
+        let (progress_tx, progress_rx) = watch::channel(DownloadProgress::default());
+        let reporter = tokio::spawn(report_progress(app, progress_rx));
+        let _reporter = AbortOnDrop(reporter);
+        let installation = GLOBAL_APP_CACHE.lock().await.installation(app, version);
+        let mut retries = 0;
+        loop {
+            match install_once(
+                &installation,
+                app,
+                version,
+                app_dir.clone(),
+                &config,
+                &progress_tx,
+            )
             .await
-            .install_once(app, version, app_dir)
-            .await?;
+            {
+                Ok(()) => break,
+                Err(err)
+                    if retries < INSTALL_RETRIES
+                        && err.downcast_ref::<InstallFailed>().is_some() =>
+                {
+                    retries += 1;
+                    tracing::warn!(app = %app.name(), %version, "{:?}, downloading again", err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
     }
 
     Ok(bin_path)
 }
 
+/// Locate the given applications and download the missing ones concurrently, returning their
+/// paths in the same order, see [`get`].
+pub async fn get_all(apps: &[(Application, Option<&str>)]) -> Result<Vec<PathBuf>> {
+    futures_util::future::try_join_all(apps.iter().map(|&(app, version)| get(app, version))).await
+}
+
+/// List the cached installations of the given application, as pairs of the version and the path to
+/// the binary.
+pub async fn cached(app: Application) -> Result<Vec<(String, PathBuf)>> {
+    let cache_dir = cache_dir().await?;
+    let prefix = format!("{}-", app.name());
+    let mut entries = tokio::fs::read_dir(&cache_dir)
+        .await
+        .context("failed reading cache directory")?;
+
+    let mut installations = Vec::new();
+    while let Some(entry) = entries
+        .next_entry()
+        .await
+        .context("failed reading cache directory entry")?
+    {
+        let is_dir = entry
+            .file_type()
+            .await
+            .context("failed reading cache directory entry")?
+            .is_dir();
+        if !is_dir {
+            // Skip temporary files of downloads.
+            continue;
+        }
+        let dir_name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(version) = dir_name.strip_prefix(&prefix) {
+            let bin_path = entry.path().join(app.path());
+            if is_executable(&bin_path).await? {
+                installations.push((version.to_owned(), bin_path));
+            }
+        }
+    }
+    installations.sort();
+
+    Ok(installations)
+}
+
 /// Try to find a globally system installed version of the application and ensure it is the needed
 /// release version.
 #[tracing::instrument(level = "trace")]
@@ -261,7 +622,7 @@ async fn find_system(app: Application, version: Option<&str>) -> Option<(PathBuf
 
     match result().await {
         Ok((path, system_version)) => version
-            .map(|v| v == system_version)
+            .map(|v| version_matches(v, &system_version))
             .unwrap_or(true)
             .then(|| (path, system_version)),
         Err(e) => {
@@ -271,61 +632,548 @@ async fn find_system(app: Application, version: Option<&str>) -> Option<(PathBuf
     }
 }
 
+/// Whether the version of a system installed application satisfies the requested version, which is
+/// either an exact version, ignoring build metadata, or a semver range like `^0.2`. Versions which
+/// are no valid semver, like `version_110` of wasm-opt, are compared as strings.
+pub(crate) fn version_matches(requested: &str, system_version: &str) -> bool {
+    let requested = strip_build_suffix(requested);
+    let req = match semver::Version::parse(requested) {
+        Ok(_) => semver::VersionReq::parse(&format!("={}", requested)),
+        Err(_) => semver::VersionReq::parse(requested),
+    };
+    match (req, semver::Version::parse(system_version)) {
+        (Ok(req), Ok(system_version)) => req.matches(&system_version),
+        _ => requested == system_version,
+    }
+}
+
+/// Whether the requested version of the application, or its default version if none is
+/// requested, is at least the given version. Version ranges and versions which are no valid
+/// semver are never known to be.
+pub(crate) fn version_at_least(app: Application, requested: Option<&str>, min: &str) -> bool {
+    let version = requested.unwrap_or_else(|| app.default_version());
+    match (
+        semver::Version::parse(strip_build_suffix(version)),
+        semver::Version::parse(min),
+    ) {
+        (Ok(version), Ok(min)) => version >= min,
+        _ => false,
+    }
+}
+
+/// Whether the requested version is a semver range like `^0.2`, rather than a single version.
+pub(crate) fn is_version_range(version: &str) -> bool {
+    semver::Version::parse(strip_build_suffix(version)).is_err()
+        && semver::VersionReq::parse(version).is_ok()
+}
+
+/// The SHA-256 digests of official release archives, by their download URL. Archives not listed
+/// here are used without verifying their integrity.
+///
+/// No digests are recorded yet, so this only takes effect once they are added.
+const KNOWN_CHECKSUMS: &[(&str, &str)] = &[];
+
+/// Check the SHA-256 digest of a downloaded archive against the expected one, if known.
+fn verify_checksum(url: &str, expected: Option<&str>, actual: &str) -> Result<()> {
+    match expected {
+        Some(expected) => ensure!(
+            expected.eq_ignore_ascii_case(actual),
+            "checksum mismatch for {}: expected SHA-256 {}, got {}; the download may be \
+             truncated or tampered with",
+            url,
+            expected,
+            actual
+        ),
+        None => tracing::warn!(
+            "no known checksum for {}, skipping the verification of the download",
+            url
+        ),
+    }
+    Ok(())
+}
+
+/// The progress of a download.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DownloadProgress {
+    /// The number of bytes received so far.
+    pub bytes_downloaded: u64,
+    /// The size of the download, unless the server didn't send a `Content-Length`.
+    pub content_length: Option<u64>,
+}
+
+impl fmt::Display for DownloadProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mib = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+        write!(f, "{:.1} MiB", mib(self.bytes_downloaded))?;
+        match self.content_length {
+            Some(len) if len > 0 => write!(
+                f,
+                " of {:.1} MiB ({}%)",
+                mib(len),
+                self.bytes_downloaded * 100 / len
+            ),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// How often the progress of a download is logged.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Log the progress of downloading the given application until the download is over, skipping
+/// intervals without progress.
+async fn report_progress(app: Application, progress: watch::Receiver<DownloadProgress>) {
+    let mut interval = tokio::time::interval(PROGRESS_INTERVAL);
+    // The first tick completes immediately, before anything was downloaded.
+    interval.tick().await;
+    let mut reported = DownloadProgress::default();
+    loop {
+        interval.tick().await;
+        let current = *progress.borrow();
+        if current.bytes_downloaded != reported.bytes_downloaded {
+            tracing::info!("downloading {}: {}", app.name(), current);
+            reported = current;
+        }
+    }
+}
+
+/// Aborts a task when dropped, like when the future awaiting it is cancelled.
+struct AbortOnDrop<T>(tokio::task::JoinHandle<T>);
+
+impl<T> Drop for AbortOnDrop<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// How failed downloads are retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct DownloadConfig {
+    /// The maximum number of attempts to download a file, including the first one.
+    attempts: u32,
+    /// The delay the retry delays are based on, the first retry being delayed by twice as much,
+    /// and every further retry by twice the delay of the previous one, see `retry_delay`.
+    base_delay: Duration,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            base_delay: Duration::from_secs(1),
+        }
+    }
+}
+
+impl DownloadConfig {
+    /// The default configuration, with the number of attempts and the base delay in seconds
+    /// overridden by `TRUNK_DOWNLOAD_ATTEMPTS` and `TRUNK_DOWNLOAD_RETRY_DELAY_SECS`.
+    fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+        if let Ok(value) = std::env::var("TRUNK_DOWNLOAD_ATTEMPTS") {
+            config.attempts = value.trim().parse().with_context(|| {
+                format!(
+                    "`TRUNK_DOWNLOAD_ATTEMPTS` must be a number of attempts, got `{}`",
+                    value
+                )
+            })?;
+            ensure!(
+                config.attempts > 0,
+                "`TRUNK_DOWNLOAD_ATTEMPTS` must be at least 1"
+            );
+        }
+        if let Ok(value) = std::env::var("TRUNK_DOWNLOAD_RETRY_DELAY_SECS") {
+            let secs = value.trim().parse().with_context(|| {
+                format!(
+                    "`TRUNK_DOWNLOAD_RETRY_DELAY_SECS` must be a number of seconds, got `{}`",
+                    value
+                )
+            })?;
+            config.base_delay = Duration::from_secs(secs);
+        }
+        Ok(config)
+    }
+
+    /// The delay before retrying after the given failed attempt, counting from 1, which is
+    /// `2^attempt` times the base delay.
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.min(31)))
+    }
+}
+
+/// A download failing with an HTTP status other than success.
+#[derive(Debug)]
+struct HttpStatusError {
+    status: reqwest::StatusCode,
+    url: String,
+}
+
+impl fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "error downloading archive file: {:?}\n{}",
+            self.status, self.url
+        )
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+/// Whether a failed download attempt is worth retrying. Connection errors and server errors are,
+/// as well as `429 Too Many Requests`, but other client errors like `404 Not Found` are not.
+fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<HttpStatusError>() {
+            err.status.is_server_error() || err.status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        } else if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+            !err.is_builder()
+        } else {
+            false
+        }
+    })
+}
+
 /// Download a file from its remote location in the given version, extract it and make it ready for
 /// execution at the given location.
-#[tracing::instrument(level = "trace")]
-async fn download(app: Application, version: &str) -> Result<PathBuf> {
+///
+/// Transient failures are retried as configured. The progress is sent after every received chunk.
+#[tracing::instrument(level = "trace", skip(config, progress))]
+async fn download(
+    app: Application,
+    version: &str,
+    config: &DownloadConfig,
+    progress: &watch::Sender<DownloadProgress>,
+) -> Result<PathBuf> {
     tracing::info!(version = version, "downloading {}", app.name());
 
     let cache_dir = cache_dir()
         .await
         .context("failed getting the cache directory")?;
+    if std::env::var("TRUNK_VALIDATE_VERSIONS").as_deref() == Ok("1") {
+        validate_version(app, version).await?;
+    }
+    let url = app.url(version)?;
+    ensure_disk_space(&cache_dir, &url).await?;
+
     let temp_out = cache_dir.join(format!("{}-{}.tmp", app.name(), version));
-    let mut file = File::create(&temp_out)
+    let mut attempt = 1;
+    let digest = loop {
+        match download_attempt(&url, &temp_out, progress).await {
+            Ok(digest) => break digest,
+            Err(err) if attempt < config.attempts && is_transient(&err) => {
+                let delay = config.retry_delay(attempt);
+                tracing::warn!(
+                    app = %app.name(),
+                    %version,
+                    "{:?}, retrying in {}s (attempt {} of {})",
+                    err,
+                    delay.as_secs(),
+                    attempt + 1,
+                    config.attempts
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    };
+
+    if let Err(err) = verify_checksum(&url, app.checksum(version), &digest) {
+        tokio::fs::remove_file(&temp_out)
+            .await
+            .context("failed deleting temporary archive")?;
+        return Err(err);
+    }
+
+    Ok(temp_out)
+}
+
+/// Download the given URL to the given file once, returning the hex encoded SHA-256 digest of
+/// the download.
+async fn download_attempt(
+    url: &str,
+    temp_out: &Path,
+    progress: &watch::Sender<DownloadProgress>,
+) -> Result<String> {
+    // Creating the file truncates anything written by a previous attempt.
+    let mut file = File::create(temp_out)
         .await
         .context("failed creating temporary output file")?;
 
-    let resp = reqwest::get(app.url(version)?)
+    let resp = http_client()
+        .get(url)
+        .send()
         .await
         .context("error sending HTTP request")?;
-    ensure!(
-        resp.status().is_success(),
-        "error downloading archive file: {:?}\n{}",
-        resp.status(),
-        app.url(version)?
-    );
-    let mut res_bytes = resp.bytes_stream();
+    if !resp.status().is_success() {
+        return Err(HttpStatusError {
+            status: resp.status(),
+            url: url.to_owned(),
+        }
+        .into());
+    }
+    let mut current = DownloadProgress {
+        bytes_downloaded: 0,
+        content_length: resp.content_length(),
+    };
+    progress.send_replace(current);
+    let mut res_bytes: Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>> =
+        match download_max_bytes_per_sec()? {
+            Some(max_bytes_per_sec) => {
+                Box::pin(ThrottledStream::new(resp.bytes_stream(), max_bytes_per_sec))
+            }
+            None => Box::pin(resp.bytes_stream()),
+        };
+    let mut hasher = Sha256::new();
     while let Some(chunk_res) = res_bytes.next().await {
         let chunk = chunk_res.context("error reading chunk from download")?;
-        let _res = file.write(chunk.as_ref()).await;
+        file.write_all(chunk.as_ref())
+            .await
+            .context("error writing download to temporary output file")?;
+        hasher.update(&chunk);
+        current.bytes_downloaded += chunk.len() as u64;
+        progress.send_replace(current);
     }
+    file.flush()
+        .await
+        .context("error writing download to temporary output file")?;
 
-    Ok(temp_out)
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The download bandwidth limit in bytes per second, configured in kilobytes per second with
+/// `TRUNK_DOWNLOAD_MAX_KBPS`.
+fn download_max_bytes_per_sec() -> Result<Option<u64>> {
+    let value = match std::env::var("TRUNK_DOWNLOAD_MAX_KBPS") {
+        Ok(value) => value,
+        Err(_) => return Ok(None),
+    };
+    let kbps: u64 = value.trim().parse().with_context(|| {
+        format!(
+            "`TRUNK_DOWNLOAD_MAX_KBPS` must be a number of kilobytes per second, got `{}`",
+            value
+        )
+    })?;
+    ensure!(kbps > 0, "`TRUNK_DOWNLOAD_MAX_KBPS` must be at least 1");
+    Ok(Some(kbps * 1024))
+}
+
+/// A stream of downloaded chunks, limited to a maximum throughput by delaying the next chunk until
+/// the bytes received so far are within the limit.
+struct ThrottledStream<S> {
+    /// The stream of downloaded chunks.
+    inner: S,
+    /// The maximum throughput in bytes per second.
+    max_bytes_per_sec: u64,
+    /// The start of the current one second window.
+    window_start: Instant,
+    /// The number of bytes received in the current window.
+    bytes_this_window: u64,
+    /// The delay before the next chunk is polled, if the limit was reached.
+    delay: Option<Pin<Box<tokio::time::Sleep>>>,
+}
+
+impl<S> ThrottledStream<S> {
+    fn new(inner: S, max_bytes_per_sec: u64) -> Self {
+        Self {
+            inner,
+            max_bytes_per_sec,
+            window_start: Instant::now(),
+            bytes_this_window: 0,
+            delay: None,
+        }
+    }
+}
+
+impl<S, E> Stream for ThrottledStream<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+{
+    type Item = Result<Bytes, E>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(delay) = self.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+
+        let item = ready!(self.inner.poll_next_unpin(cx));
+        if let Some(Ok(chunk)) = &item {
+            self.bytes_this_window += chunk.len() as u64;
+            let elapsed = self.window_start.elapsed();
+            let expected = Duration::from_secs_f64(
+                self.bytes_this_window as f64 / self.max_bytes_per_sec as f64,
+            );
+            if expected > elapsed {
+                self.delay = Some(Box::pin(tokio::time::sleep(expected - elapsed)));
+            }
+            // Start a new window once the current one is over, so that a slow start does not allow
+            // for bursts later on.
+            if expected.max(elapsed) >= Duration::from_secs(1) {
+                self.window_start = Instant::now() + expected.saturating_sub(elapsed);
+                self.bytes_this_window = 0;
+            }
+        }
+        Poll::Ready(item)
+    }
+}
+
+/// Make sure a release of the given version exists, using the GitHub REST API, to fail with a
+/// helpful error instead of a failing download.
+///
+/// This is only done when enabled with `TRUNK_VALIDATE_VERSIONS=1`, as the API is rate limited.
+/// If the API can't be reached or answers with an unexpected status, the check is skipped.
+async fn validate_version(app: Application, version: &str) -> Result<()> {
+    let repo = app.github_repo();
+    let url = format!(
+        "https://api.github.com/repos/{}/releases/tags/{}",
+        repo,
+        app.release_tag(version)
+    );
+    let resp = http_client()
+        .get(&url)
+        // The GitHub API rejects requests without a user agent.
+        .header(reqwest::header::USER_AGENT, "trunk")
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await;
+
+    match resp {
+        Ok(resp) if resp.status().is_success() => Ok(()),
+        Ok(resp) if resp.status() == reqwest::StatusCode::NOT_FOUND => bail!(
+            "{} version {} does not exist, see https://github.com/{}/releases for the available \
+             versions",
+            app.name(),
+            version,
+            repo
+        ),
+        Ok(resp) => {
+            tracing::warn!(
+                "skipping validation of {} version {}, the GitHub API answered with {}",
+                app.name(),
+                version,
+                resp.status()
+            );
+            Ok(())
+        }
+        Err(err) => {
+            tracing::warn!(
+                "skipping validation of {} version {}, the GitHub API is not reachable: {}",
+                app.name(),
+                version,
+                err
+            );
+            Ok(())
+        }
+    }
+}
+
+/// The version of the latest release of the application, using the GitHub REST API.
+pub async fn latest_version(app: Application) -> Result<String> {
+    #[derive(Deserialize)]
+    struct Release {
+        tag_name: String,
+    }
+
+    let url = format!(
+        "https://api.github.com/repos/{}/releases/latest",
+        app.github_repo()
+    );
+    let release = http_client()
+        .get(&url)
+        // The GitHub API rejects requests without a user agent.
+        .header(reqwest::header::USER_AGENT, "trunk")
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status())
+        .with_context(|| format!("error fetching the latest release of {}", app.name()))?
+        .bytes()
+        .await
+        .with_context(|| format!("error reading the latest release of {}", app.name()))?;
+    let release: Release = serde_json::from_slice(&release)
+        .with_context(|| format!("error parsing the latest release of {}", app.name()))?;
+    // Undo the prefix of the release tag, see `Application::release_tag`.
+    Ok(match app {
+        Application::WasmTools | Application::Wizer => release
+            .tag_name
+            .strip_prefix('v')
+            .map(str::to_owned)
+            .unwrap_or(release.tag_name),
+        _ => release.tag_name,
+    })
+}
+
+/// Make sure there is enough disk space available in the cache dir to download and extract the
+/// archive at the given URL, to fail early instead of running out of space halfway.
+///
+/// The archive size is determined upfront through a HEAD request. If the size is unknown, the
+/// check is skipped.
+async fn ensure_disk_space(cache_dir: &Path, url: &str) -> Result<()> {
+    // The body of a HEAD response is empty, so `Response::content_length` is always zero and the
+    // header has to be parsed instead.
+    let archive_size = match http_client().head(url).send().await.ok().and_then(|resp| {
+        resp.headers()
+            .get(reqwest::header::CONTENT_LENGTH)?
+            .to_str()
+            .ok()?
+            .parse::<u64>()
+            .ok()
+    }) {
+        Some(size) => size,
+        None => {
+            tracing::debug!("archive size unknown, skipping disk space check");
+            return Ok(());
+        }
+    };
+
+    let available =
+        fs2::available_space(cache_dir).context("failed checking available disk space")?;
+    check_disk_space(archive_size, available, url, cache_dir)
+}
+
+/// Check that the available disk space suffices for an archive of the given size.
+fn check_disk_space(archive_size: u64, available: u64, url: &str, cache_dir: &Path) -> Result<()> {
+    // Leave room for the extracted files next to the archive itself.
+    let required = archive_size.saturating_mul(2);
+    ensure!(
+        available >= required,
+        "not enough disk space to download {}: {} bytes required (twice the archive size), but \
+         only {} bytes are available in {}",
+        url,
+        required,
+        available,
+        cache_dir.display()
+    );
+
+    Ok(())
 }
 
 /// Install an application from a downloaded archive locating and copying it to the given target
 /// location.
 #[tracing::instrument(level = "trace")]
-async fn install(app: Application, archive_file: File, target: PathBuf) -> Result<()> {
+async fn install(
+    app: Application,
+    version: &str,
+    archive_path: PathBuf,
+    target: PathBuf,
+) -> Result<()> {
     tracing::info!("installing {}", app.name());
 
-    let archive_file = archive_file.into_std().await;
+    let format = app.archive_format(version)?;
+    let paths: Vec<&str> = std::iter::once(app.path())
+        .chain(app.extra_paths().iter().copied())
+        .collect();
 
     tokio::task::spawn_blocking(move || {
-        let mut archive = if app == Application::Sass && cfg!(target_os = "windows") {
-            Archive::new_zip(archive_file)?
-        } else {
-            Archive::new_tar_gz(archive_file)
-        };
-        archive.extract_file(app.path(), &target)?;
-
-        for path in app.extra_paths() {
-            // After extracting one file the archive must be reset.
-            archive = archive.reset()?;
-            archive.extract_file(path, &target)?;
-        }
-
-        Ok(())
+        // Every file is extracted with its own handle to the archive, so they can be extracted in
+        // parallel instead of scanning the archive again from the start for each of them.
+        paths.par_iter().try_for_each(|path| {
+            let mut archive = Archive::open(&archive_path, format)?;
+            archive.extract_file(path, &target)
+        })
     })
     .await?
 }
@@ -344,16 +1192,41 @@ pub async fn cache_dir() -> Result<PathBuf> {
 
 mod archive {
     use std::fs::{self, File};
-    use std::io::{self, BufReader, Read, Seek, SeekFrom};
+    use std::io::{self, BufReader, Read, Seek};
     use std::path::Path;
 
-    use anyhow::{Context, Result};
+    use anyhow::{bail, Context, Result};
     use flate2::read::GzDecoder;
     use tar::{Archive as TarArchive, Entry as TarEntry};
+    use xz2::read::XzDecoder;
     use zip::ZipArchive;
 
+    /// The formats of release archives.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum ArchiveFormat {
+        TarGz,
+        TarXz,
+        Zip,
+    }
+
+    impl ArchiveFormat {
+        /// The format of the archive with the given file name or URL, by its extension.
+        pub fn from_file_name(name: &str) -> Result<Self> {
+            Ok(if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+                Self::TarGz
+            } else if name.ends_with(".tar.xz") {
+                Self::TarXz
+            } else if name.ends_with(".zip") {
+                Self::Zip
+            } else {
+                bail!("unknown archive format of {}", name)
+            })
+        }
+    }
+
     pub enum Archive {
         TarGz(Box<TarArchive<GzDecoder<BufReader<File>>>>),
+        TarXz(Box<TarArchive<XzDecoder<BufReader<File>>>>),
         Zip(ZipArchive<BufReader<File>>),
     }
 
@@ -364,21 +1237,30 @@ mod archive {
             )))))
         }
 
+        pub fn new_tar_xz(file: File) -> Self {
+            Self::TarXz(Box::new(TarArchive::new(XzDecoder::new(BufReader::new(
+                file,
+            )))))
+        }
+
         pub fn new_zip(file: File) -> Result<Self> {
             Ok(Self::Zip(ZipArchive::new(BufReader::new(file))?))
         }
 
+        /// Open the archive of the given format at the given path.
+        pub fn open(path: &Path, format: ArchiveFormat) -> Result<Self> {
+            let file = File::open(path).context("failed opening downloaded file")?;
+            match format {
+                ArchiveFormat::TarGz => Ok(Self::new_tar_gz(file)),
+                ArchiveFormat::TarXz => Ok(Self::new_tar_xz(file)),
+                ArchiveFormat::Zip => Self::new_zip(file),
+            }
+        }
+
         pub fn extract_file(&mut self, file: &str, target: &Path) -> Result<()> {
             match self {
-                Self::TarGz(archive) => {
-                    let mut tar_file =
-                        find_tar_entry(archive, file)?.context("file not found in archive")?;
-                    let mut out_file = extract_file(&mut tar_file, file, target)?;
-
-                    if let Ok(mode) = tar_file.header().mode() {
-                        set_file_permissions(&mut out_file, mode)?;
-                    }
-                }
+                Self::TarGz(archive) => extract_tar_file(archive, file, target)?,
+                Self::TarXz(archive) => extract_tar_file(archive, file, target)?,
                 Self::Zip(archive) => {
                     let zip_index =
                         find_zip_entry(archive, file)?.context("file not found in archive")?;
@@ -393,22 +1275,22 @@ mod archive {
 
             Ok(())
         }
+    }
 
-        pub fn reset(self) -> Result<Self> {
-            match self {
-                Self::TarGz(archive) => {
-                    let mut archive_file = archive.into_inner().into_inner();
-                    archive_file
-                        .seek(SeekFrom::Start(0))
-                        .context("error seeking to beginning of archive")?;
-
-                    Ok(Self::TarGz(Box::new(TarArchive::new(GzDecoder::new(
-                        archive_file,
-                    )))))
-                }
-                Self::Zip(archive) => Ok(Self::Zip(archive)),
-            }
+    /// Extract a file from a TAR archive, keeping its permissions.
+    fn extract_tar_file(
+        archive: &mut TarArchive<impl Read>,
+        file: &str,
+        target: &Path,
+    ) -> Result<()> {
+        let mut tar_file = find_tar_entry(archive, file)?.context("file not found in archive")?;
+        let mut out_file = extract_file(&mut tar_file, file, target)?;
+
+        if let Ok(mode) = tar_file.header().mode() {
+            set_file_permissions(&mut out_file, mode)?;
         }
+
+        Ok(())
     }
 
     /// Find an entry in a TAR archive by name and open it for reading. The first part of the path
@@ -502,19 +1384,291 @@ mod tests {
             Application::Sass,
             Application::WasmBindgen,
             Application::WasmOpt,
+            Application::WasmTools,
+            Application::Wizer,
         ] {
-            let path = download(app, app.default_version())
-                .await
-                .context("error downloading app")?;
-            let file = File::open(&path).await.context("error opening file")?;
-            install(app, file, dir.path().to_owned())
-                .await
-                .context("error installing app")?;
+            let (progress, _) = watch::channel(DownloadProgress::default());
+            let path = download(
+                app,
+                app.default_version(),
+                &DownloadConfig::default(),
+                &progress,
+            )
+            .await
+            .context("error downloading app")?;
+            install(
+                app,
+                app.default_version(),
+                path.clone(),
+                dir.path().to_owned(),
+            )
+            .await
+            .context("error installing app")?;
             std::fs::remove_file(path).context("error during cleanup")?;
         }
         Ok(())
     }
 
+    #[tokio::test]
+    async fn throttled_stream_limits_throughput() -> Result<()> {
+        let chunks = (0..4).map(|_| Ok::<_, anyhow::Error>(Bytes::from(vec![0; 1000])));
+        let mut stream = ThrottledStream::new(futures_util::stream::iter(chunks), 10_000);
+
+        let start = Instant::now();
+        let mut received = 0;
+        while let Some(chunk) = stream.next().await {
+            received += chunk?.len();
+        }
+        assert_eq!(received, 4000);
+        // The delay after the last chunk is never awaited.
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        Ok(())
+    }
+
+    #[test]
+    fn verify_download_checksum() -> Result<()> {
+        let url = "https://example.com/tool.tar.gz";
+        // The digest of an empty archive.
+        let digest = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        verify_checksum(url, Some(digest), digest)?;
+        verify_checksum(url, Some(&digest.to_uppercase()), digest)?;
+        verify_checksum(url, None, digest)?;
+
+        let err = verify_checksum(url, Some(&"0".repeat(64)), digest)
+            .err()
+            .context("mismatching checksum was accepted")?;
+        ensure!(err.to_string().contains(digest), "actual digest missing");
+        Ok(())
+    }
+
+    #[test]
+    fn disk_space_check() -> Result<()> {
+        let url = "https://example.com/tool.tar.gz";
+        let cache_dir = Path::new("/cache");
+        check_disk_space(10_000_000, 20_000_000, url, cache_dir)?;
+        check_disk_space(0, 0, url, cache_dir)?;
+
+        let err = check_disk_space(10_000_000, 1_000, url, cache_dir)
+            .err()
+            .context("insufficient disk space was accepted")?;
+        ensure!(
+            err.to_string().contains("20000000 bytes required"),
+            "required space missing"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn download_retries() -> Result<()> {
+        let config = DownloadConfig::default();
+        ensure!(
+            config.retry_delay(1) == Duration::from_secs(2),
+            "first retry delay"
+        );
+        ensure!(
+            config.retry_delay(2) == Duration::from_secs(4),
+            "second retry delay"
+        );
+
+        let status_err = |status| -> anyhow::Error {
+            HttpStatusError {
+                status,
+                url: "https://example.com/tool.tar.gz".to_owned(),
+            }
+            .into()
+        };
+        ensure!(
+            is_transient(&status_err(reqwest::StatusCode::SERVICE_UNAVAILABLE)),
+            "503 is not retried"
+        );
+        ensure!(
+            is_transient(&status_err(reqwest::StatusCode::TOO_MANY_REQUESTS)),
+            "429 is not retried"
+        );
+        ensure!(
+            !is_transient(&status_err(reqwest::StatusCode::NOT_FOUND)),
+            "404 is retried"
+        );
+        ensure!(
+            !is_transient(&anyhow::anyhow!("checksum mismatch")),
+            "other errors are retried"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn wasm_bindgen_url_for_aarch64_linux() -> Result<()> {
+        let url = Application::WasmBindgen.url_for("0.2.92", "linux", "aarch64")?;
+        ensure!(
+            !url.contains("x86_64"),
+            "x86_64 binary for aarch64: {}",
+            url
+        );
+        ensure!(
+            url.ends_with("wasm-bindgen-0.2.92-aarch64-unknown-linux-musl.tar.gz"),
+            "unexpected URL: {}",
+            url
+        );
+        let url = Application::WasmBindgen.url_for("0.2.92", "linux", "x86_64")?;
+        ensure!(
+            url.ends_with("wasm-bindgen-0.2.92-x86_64-unknown-linux-musl.tar.gz"),
+            "unexpected URL: {}",
+            url
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn no_proxy_hosts() {
+        let no_proxy = "localhost, .internal.example.com,mirror.example.org";
+        assert!(bypasses_proxy(no_proxy, "localhost"));
+        assert!(bypasses_proxy(no_proxy, "internal.example.com"));
+        assert!(bypasses_proxy(no_proxy, "dl.internal.example.com"));
+        assert!(bypasses_proxy(no_proxy, "Mirror.Example.org"));
+        assert!(!bypasses_proxy(no_proxy, "github.com"));
+        assert!(!bypasses_proxy(no_proxy, "notlocalhost"));
+        assert!(!bypasses_proxy("", "github.com"));
+        assert!(bypasses_proxy("*", "github.com"));
+    }
+
+    #[tokio::test]
+    async fn env_proxy_is_used() -> Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let proxy_url = format!("http://{}", listener.local_addr()?);
+        let proxy = env_proxy(|name| match name {
+            "http_proxy" => Some(proxy_url.clone()),
+            "NO_PROXY" => Some("localhost".to_owned()),
+            _ => None,
+        });
+        assert_eq!(proxy.http_url.as_deref(), Some(proxy_url.as_str()));
+        assert_eq!(proxy.https_url, None);
+
+        // The fake proxy receives the request, and closes the connection without answering.
+        let proxy_server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await?;
+            let mut request = vec![0; 1024];
+            let len = tokio::io::AsyncReadExt::read(&mut stream, &mut request).await?;
+            request.truncate(len);
+            anyhow::Ok(String::from_utf8_lossy(&request).into_owned())
+        });
+        let err = build_http_client(&proxy)?
+            .get("http://example.com/tool.tar.gz")
+            .send()
+            .await
+            .err()
+            .context("request through the fake proxy succeeded")?;
+        ensure!(!err.is_builder(), "request was not sent: {}", err);
+        let request = proxy_server.await??;
+        ensure!(
+            request.starts_with("GET http://example.com/tool.tar.gz "),
+            "unexpected request to the proxy: {}",
+            request
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn archive_format_from_url() -> Result<()> {
+        let format = |app: Application, target_os| {
+            app.url_for(app.default_version(), target_os, "x86_64")
+                .and_then(|url| ArchiveFormat::from_file_name(&url))
+        };
+        ensure!(format(Application::Wizer, "linux")? == ArchiveFormat::TarXz);
+        ensure!(format(Application::Wizer, "windows")? == ArchiveFormat::Zip);
+        ensure!(format(Application::WasmOpt, "linux")? == ArchiveFormat::TarGz);
+        ensure!(format(Application::Sass, "windows")? == ArchiveFormat::Zip);
+        ensure!(ArchiveFormat::from_file_name("tool.tar.bz2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn configured_download_url() {
+        let urls = ConfigOptsToolsDownloadUrls {
+            wasm_bindgen: Some("https://mirror.example.com/wasm-bindgen/{version}.tar.gz".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            Application::WasmBindgen.download_url(&urls),
+            urls.wasm_bindgen.as_deref()
+        );
+        assert_eq!(Application::Sass.download_url(&urls), None);
+    }
+
+    #[tokio::test]
+    async fn offline_missing_tool() -> Result<()> {
+        let err = get_with(Application::Wizer, Some("0.0.1"), true)
+            .await
+            .err()
+            .context("missing tool was downloaded in offline mode")?;
+        ensure!(
+            err.to_string().contains("wizer 0.0.1"),
+            "tool missing in error: {}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn system_version_matching() {
+        // Exact versions, ignoring build metadata.
+        assert!(version_matches("0.2.83", "0.2.83"));
+        assert!(version_matches("0.2.83+git.abc1234", "0.2.83"));
+        assert!(!version_matches("0.2.83", "0.2.84"));
+        assert!(!version_matches("1.0.0", "1.0.0-rc.1"));
+        // Ranges.
+        assert!(version_matches("^0.2", "0.2.92"));
+        assert!(version_matches(">=1.70, <2", "1.71.0"));
+        assert!(!version_matches("^0.2", "0.3.0"));
+        assert!(is_version_range("^0.2"));
+        assert!(!is_version_range("0.2.83"));
+        // Versions which are no semver.
+        assert!(version_matches("version_110", "version_110"));
+        assert!(!version_matches("version_110", "version_111"));
+        assert!(!version_matches("^0.2", "not-a-version"));
+        assert!(!is_version_range("version_110"));
+    }
+
+    #[test]
+    fn version_at_least_min() {
+        assert!(version_at_least(Application::Sass, None, "1.71.0"));
+        assert!(version_at_least(
+            Application::Sass,
+            Some("1.77.8"),
+            "1.74.0"
+        ));
+        assert!(version_at_least(
+            Application::Sass,
+            Some("1.74.0+git"),
+            "1.74.0"
+        ));
+        assert!(!version_at_least(
+            Application::Sass,
+            Some("1.69.5"),
+            "1.71.0"
+        ));
+        assert!(!version_at_least(
+            Application::Sass,
+            Some("^1.71"),
+            "1.71.0"
+        ));
+        assert!(!version_at_least(Application::WasmOpt, None, "1.0.0"));
+    }
+
+    #[test]
+    fn display_download_progress() {
+        let progress = DownloadProgress {
+            bytes_downloaded: 3 * 1024 * 1024,
+            content_length: Some(12 * 1024 * 1024),
+        };
+        assert_eq!(progress.to_string(), "3.0 MiB of 12.0 MiB (25%)");
+        // Without `Content-Length`, only the received bytes are known.
+        let progress = DownloadProgress {
+            content_length: None,
+            ..progress
+        };
+        assert_eq!(progress.to_string(), "3.0 MiB");
+    }
+
     macro_rules! table_test_format_version {
         ($name:ident, $app:expr, $input:literal, $expect:literal) => {
             #[test]
@@ -562,5 +1716,75 @@ mod tests {
         "0.2.74"
     );
 
+    table_test_format_version!(
+        wasm_bindgen_dirty_worktree,
+        Application::WasmBindgen,
+        "wasm-bindgen 0.2.75-dirty (27c7a4d06-dirty)",
+        "0.2.75"
+    );
+
+    table_test_format_version!(
+        wasm_bindgen_build_metadata,
+        Application::WasmBindgen,
+        "wasm-bindgen 0.2.83+git.abc1234",
+        "0.2.83"
+    );
+
+    table_test_format_version!(
+        wasm_opt_dirty_worktree,
+        Application::WasmOpt,
+        "wasm-opt version 110-dirty (version_110-12-g6a3b9c1-dirty)",
+        "version_110"
+    );
+
+    table_test_format_version!(
+        wasm_tools_pre_compiled,
+        Application::WasmTools,
+        "wasm-tools 1.200.0 (c7e1d8ffd 2024-02-26)",
+        "1.200.0"
+    );
+
+    table_test_format_version!(
+        wasm_bindgen_pre_release,
+        Application::WasmBindgen,
+        "wasm-bindgen 0.2.84-preview.0 (a3b2c1d0e)",
+        "0.2.84-preview.0"
+    );
+
+    table_test_format_version!(
+        wasm_bindgen_pre_release_dirty_worktree,
+        Application::WasmBindgen,
+        "wasm-bindgen 0.2.84-preview.0-dirty",
+        "0.2.84-preview.0"
+    );
+
+    table_test_format_version!(
+        wasm_opt_pre_release,
+        Application::WasmOpt,
+        "wasm-opt version 112-alpha (version_112-alpha)",
+        "version_112-alpha"
+    );
+
+    table_test_format_version!(
+        sass_pre_release,
+        Application::Sass,
+        "1.72.0-beta.1",
+        "1.72.0-beta.1"
+    );
+
+    table_test_format_version!(
+        wizer_pre_compiled,
+        Application::Wizer,
+        "wizer 3.0.1",
+        "3.0.1"
+    );
+
     table_test_format_version!(sass_pre_compiled, Application::Sass, "1.37.5", "1.37.5");
+
+    table_test_format_version!(
+        sass_dirty_worktree,
+        Application::Sass,
+        "1.54.9-dirty",
+        "1.54.9"
+    );
 }
@@ -0,0 +1,146 @@
+//! Recording of build timings in the Chrome trace format.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde_json::json;
+use tracing::span::{Attributes, Id};
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::layer::{Context as LayerContext, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+/// The name of the trace file written to the dist dir.
+pub const TRACE_FILE: &str = "trunk-trace.json";
+
+/// A tracing layer recording the duration of the spans of the build stages, like the asset
+/// pipelines and the wasm-bindgen and wasm-opt invocations, to write them as a trace which can be
+/// viewed with `chrome://tracing`.
+#[derive(Clone, Debug)]
+pub struct TraceRecorder {
+    /// The time the recording started, trace events are relative to it.
+    origin: Instant,
+    /// The recorded spans.
+    events: Arc<Mutex<Vec<TraceEvent>>>,
+}
+
+/// A span recorded by the [`TraceRecorder`].
+#[derive(Clone, Debug)]
+struct TraceEvent {
+    /// The name of the span, prefixed with the module it belongs to, like `sass::run`.
+    name: String,
+    /// The start of the span, relative to the start of the recording.
+    start: Duration,
+    /// The time from creating the span until it was closed.
+    duration: Duration,
+}
+
+/// The creation time of a span, stored in its extensions.
+struct SpanStart(Instant);
+
+impl Default for TraceRecorder {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+            events: Default::default(),
+        }
+    }
+}
+
+impl TraceRecorder {
+    /// Whether the spans of the given callsite are recorded.
+    ///
+    /// Only the build system and the pipelines are of interest. The `spawn` functions only start
+    /// the asynchronous work and return immediately, so they are left out.
+    pub fn is_recorded(metadata: &Metadata<'_>) -> bool {
+        metadata.is_span()
+            && (metadata.target().starts_with("trunk::pipelines")
+                || metadata.target().starts_with("trunk::build"))
+            && metadata.name() != "spawn"
+    }
+
+    /// Write the recorded spans as Chrome trace JSON to the given file.
+    pub async fn write(&self, path: &Path) -> Result<()> {
+        let mut events = self
+            .events
+            .lock()
+            .map_err(|_| anyhow::anyhow!("trace recorder lock poisoned"))?
+            .clone();
+        events.sort_by_key(|event| event.start);
+
+        // Spans run concurrently, so they are spread over multiple rows ("threads") of the trace,
+        // nesting spans in the row of the span containing them.
+        let mut lanes: Vec<Vec<Duration>> = vec![];
+        let mut trace_events = vec![];
+        for event in events {
+            let end = event.start + event.duration;
+            let lane = lanes.iter_mut().position(|ends| {
+                while matches!(ends.last(), Some(last) if *last <= event.start) {
+                    ends.pop();
+                }
+                !matches!(ends.last(), Some(last) if *last < end)
+            });
+            let lane = match lane {
+                Some(lane) => lane,
+                None => {
+                    lanes.push(vec![]);
+                    lanes.len() - 1
+                }
+            };
+            lanes[lane].push(end);
+            trace_events.push(json!({
+                "name": event.name,
+                "cat": "build",
+                "ph": "X",
+                "ts": event.start.as_micros() as u64,
+                "dur": event.duration.as_micros() as u64,
+                "pid": 1,
+                "tid": lane + 1,
+            }));
+        }
+
+        let trace = json!({ "traceEvents": trace_events });
+        let trace = serde_json::to_vec_pretty(&trace).context("error serializing build trace")?;
+        tokio::fs::write(path, trace)
+            .await
+            .with_context(|| format!("error writing build trace to {:?}", path))?;
+        tracing::info!("wrote build trace to {:?}", path);
+        Ok(())
+    }
+}
+
+impl<S> Layer<S> for TraceRecorder
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: LayerContext<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let started = match span.extensions().get::<SpanStart>() {
+            Some(SpanStart(started)) => *started,
+            None => return,
+        };
+        let metadata = span.metadata();
+        let module = metadata
+            .module_path()
+            .and_then(|path| path.rsplit("::").next())
+            .unwrap_or_default();
+        let event = TraceEvent {
+            name: format!("{}::{}", module, metadata.name()),
+            start: started.saturating_duration_since(self.origin),
+            duration: started.elapsed(),
+        };
+        if let Ok(mut events) = self.events.lock() {
+            events.push(event);
+        }
+    }
+}
@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -10,6 +11,7 @@ use tokio_stream::wrappers::BroadcastStream;
 
 use crate::build::BuildSystem;
 use crate::config::RtcWatch;
+use crate::pipelines::StageTiming;
 
 /// Blacklisted path segments which are ignored by the watcher by default.
 const BLACKLIST: [&str; 1] = [".git"];
@@ -24,12 +26,16 @@ pub struct WatchSystem {
     watch_rx: mpsc::Receiver<DebouncedEvent>,
     /// A channel of new paths to ignore from the build system.
     build_rx: mpsc::Receiver<PathBuf>,
+    /// A channel which is sent on whenever sass finished compiling a changed stylesheet.
+    sass_rx: mpsc::Receiver<()>,
     /// The watch system used for watching the filesystem.
     _watcher: RecommendedWatcher,
     /// The application shutdown channel.
     shutdown: BroadcastStream<()>,
     /// Channel that is sent on whenever a build completes.
     build_done_tx: Option<broadcast::Sender<()>>,
+    /// The content of the changed files, used to skip builds which would not change anything.
+    state: IncrementalBuildState,
 }
 
 impl WatchSystem {
@@ -46,6 +52,17 @@ impl WatchSystem {
         // Build the watcher.
         let _watcher = build_watcher(watch_tx, cfg.paths.clone())?;
 
+        let sass_rx = cfg
+            .build
+            .sass_daemons
+            .take_rebuild_rx()
+            .context("the sass processes are already watched by another watch system")?;
+
+        // Record the content of the watched files before the first build, so that touching a file
+        // without changing it does not trigger a build, even if it did not change before.
+        let mut state = IncrementalBuildState::default();
+        state.seed(&cfg.paths, &cfg.ignored_paths).await?;
+
         // Build dependencies.
         let build = BuildSystem::new(cfg.build.clone(), Some(build_tx)).await?;
         Ok(Self {
@@ -53,16 +70,20 @@ impl WatchSystem {
             ignored_paths: cfg.ignored_paths.clone(),
             watch_rx,
             build_rx,
+            sass_rx,
             _watcher,
             shutdown: BroadcastStream::new(shutdown.subscribe()),
             build_done_tx,
+            state,
         })
     }
 
     /// Run a build.
     #[tracing::instrument(level = "trace", skip(self))]
     pub async fn build(&mut self) -> Result<()> {
-        self.build.build().await
+        self.build.build().await?;
+        log_build_summary(self.build.timings());
+        Ok(())
     }
 
     /// Run the watch system, responding to events and triggering builds.
@@ -72,6 +93,10 @@ impl WatchSystem {
             tokio::select! {
                 Some(ign) = self.build_rx.recv() => self.update_ignore_list(ign),
                 Some(ev) = self.watch_rx.recv() => self.handle_watch_event(ev).await,
+                Some(()) = self.sass_rx.recv() => {
+                    tracing::debug!("sass finished compiling a changed stylesheet");
+                    self.rebuild().await;
+                }
                 _ = self.shutdown.next() => break, // Any event, even a drop, will trigger shutdown.
             }
         }
@@ -118,8 +143,18 @@ impl WatchSystem {
             return; // Don't emit a notification as path is on the blacklist.
         }
 
+        if !self.state.record(&ev_path).await {
+            tracing::info!("skipping build, the content of {:?} is unchanged", ev_path);
+            return;
+        }
+
         tracing::debug!("change detected in {:?}", ev_path);
-        let _res = self.build.build().await;
+        self.rebuild().await;
+    }
+
+    /// Run a build in response to a change, notifying the listeners once it is done.
+    async fn rebuild(&mut self) {
+        let _res = self.build().await;
 
         // TODO/NOTE: in the future, we will want to be able to pass along error info and other
         // diagnostics info over the socket for use in an error overlay or console logging.
@@ -140,6 +175,97 @@ impl WatchSystem {
     }
 }
 
+/// The state carried from one build of the watch system to the next.
+///
+/// Editors and tools often touch files without changing their content, so the content hash of
+/// each changed file is recorded to skip builds with unchanged inputs.
+#[derive(Default)]
+pub struct IncrementalBuildState {
+    /// The content hash of each changed file, `None` if it could not be read.
+    hashes: HashMap<PathBuf, Option<u64>>,
+}
+
+impl IncrementalBuildState {
+    /// Record the current content of the given path, returning `true` if it changed since it was
+    /// last recorded. Paths which were not recorded before are always considered changed, as are
+    /// paths which can not be read, like directories or deleted files.
+    pub async fn record(&mut self, path: &Path) -> bool {
+        let hash = tokio::fs::read(path)
+            .await
+            .ok()
+            .map(|bytes| seahash::hash(&bytes));
+        let previous = self.hashes.insert(path.to_owned(), hash);
+        hash.is_none() || previous != Some(hash)
+    }
+
+    /// Record the content of all files below the given paths. Ignored and blacklisted paths are
+    /// skipped, as are cache dirs marked with a `CACHEDIR.TAG` file, like cargo's target dir.
+    pub async fn seed(&mut self, paths: &[PathBuf], ignored_paths: &[PathBuf]) -> Result<()> {
+        let paths = paths.to_vec();
+        let ignored_paths = ignored_paths.to_vec();
+        let hashes = tokio::task::spawn_blocking(move || {
+            let mut hashes = HashMap::new();
+            for path in &paths {
+                hash_files(path, &ignored_paths, &mut hashes);
+            }
+            hashes
+        })
+        .await
+        .context("error awaiting spawned file hashing")?;
+        self.hashes
+            .extend(hashes.into_iter().map(|(path, hash)| (path, Some(hash))));
+        Ok(())
+    }
+}
+
+/// Hash the content of all files below the given path into `hashes`, skipping paths which can
+/// not be read. Symlinks are not followed.
+fn hash_files(path: &Path, ignored_paths: &[PathBuf], hashes: &mut HashMap<PathBuf, u64>) {
+    let is_blacklisted = matches!(
+        path.file_name().and_then(|name| name.to_str()),
+        Some(name) if BLACKLIST.contains(&name)
+    );
+    if is_blacklisted || ignored_paths.iter().any(|ignored| ignored == path) {
+        return;
+    }
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return,
+    };
+    if metadata.is_file() {
+        if let Ok(bytes) = std::fs::read(path) {
+            hashes.insert(path.to_owned(), seahash::hash(&bytes));
+        }
+    } else if metadata.is_dir() && !path.join("CACHEDIR.TAG").exists() {
+        for entry in std::fs::read_dir(path).into_iter().flatten().flatten() {
+            hash_files(&entry.path(), ignored_paths, hashes);
+        }
+    }
+}
+
+/// Log how long each stage of the last build took, and which of their steps were skipped.
+fn log_build_summary(timings: &[StageTiming]) {
+    tracing::info!("build stages:\n{}", build_summary(timings));
+}
+
+/// Describe how long each stage of a build took, and which of their steps were skipped.
+fn build_summary(timings: &[StageTiming]) -> String {
+    timings
+        .iter()
+        .map(|timing| {
+            let mut line = format!("    {}: {:.2?}", timing.name, timing.duration);
+            if !timing.skipped.is_empty() {
+                line.push_str(&format!(
+                    " (skipped {}, input unchanged)",
+                    timing.skipped.join(", ")
+                ));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 /// Build a FS watcher, when the watcher is dropped, it will stop watching for events.
 fn build_watcher(
     watch_tx: mpsc::Sender<DebouncedEvent>,
@@ -173,3 +299,73 @@ fn build_watcher(
 
     Ok(watcher)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn incremental_build_state_records_changes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let file = dir.path().join("index.html");
+        let mut state = IncrementalBuildState::default();
+
+        tokio::fs::write(&file, "a").await?;
+        assert!(state.record(&file).await);
+        assert!(!state.record(&file).await);
+        tokio::fs::write(&file, "b").await?;
+        assert!(state.record(&file).await);
+
+        // Unreadable paths are never skipped.
+        tokio::fs::remove_file(&file).await?;
+        assert!(state.record(&file).await);
+        assert!(state.record(&file).await);
+        assert!(state.record(dir.path()).await);
+        assert!(state.record(dir.path()).await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn incremental_build_state_seeds_watched_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let dir = dir.path().canonicalize()?;
+        let file = dir.join("index.html");
+        let ignored = dir.join("dist");
+        let cache = dir.join("target");
+        for sub_dir in [&ignored, &cache] {
+            tokio::fs::create_dir(sub_dir).await?;
+            tokio::fs::write(sub_dir.join("file"), "a").await?;
+        }
+        tokio::fs::write(cache.join("CACHEDIR.TAG"), "").await?;
+        tokio::fs::write(&file, "a").await?;
+
+        let mut state = IncrementalBuildState::default();
+        state
+            .seed(std::slice::from_ref(&dir), std::slice::from_ref(&ignored))
+            .await?;
+        assert!(!state.record(&file).await);
+        assert!(state.record(&ignored.join("file")).await);
+        assert!(state.record(&cache.join("file")).await);
+        Ok(())
+    }
+
+    #[test]
+    fn build_summary_lists_skipped_steps() {
+        let timings = [
+            StageTiming {
+                name: "rust #1".into(),
+                duration: Duration::from_secs(1),
+                skipped: vec!["wasm-bindgen"],
+            },
+            StageTiming {
+                name: "build hooks".into(),
+                duration: Duration::from_secs(0),
+                skipped: vec![],
+            },
+        ];
+        assert_eq!(
+            build_summary(&timings),
+            "    rust #1: 1.00s (skipped wasm-bindgen, input unchanged)\n    build hooks: 0.00ns"
+        );
+    }
+}